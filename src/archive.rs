@@ -0,0 +1,323 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::LinkError;
+use crate::object::ObjectFile;
+
+/// The signature at the start of every `ar` archive.
+pub(crate) const ARCHIVE_MAGIC: &[u8; 8] = b"!<arch>\n";
+
+const HEADER_SIZE: usize = 60;
+const HEADER_END: &[u8; 2] = b"`\n";
+const SYMBOL_INDEX_NAME: &str = "/";
+const LONG_NAMES_NAME: &str = "//";
+const BSD_NAME_PREFIX: &str = "#1/";
+
+pub struct Member {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// An `ar` archive in the System V/GNU layout, as written by `ar` on Linux.
+/// BSD-style long member names are also understood when reading.
+#[derive(Default)]
+pub struct Archive {
+    pub members: Vec<Member>,
+    /// The symbols each member defines, from the archive's symbol index, as
+    /// pairs of symbol name and member index. `None` without an index.
+    pub(crate) symbols: Option<Vec<(String, usize)>>,
+}
+
+impl Archive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(path: &Path, buffer: &[u8]) -> Result<Self, LinkError> {
+        let error = |offset: usize, reason: &'static str| LinkError::InvalidArchive {
+            path: path.to_owned(),
+            offset,
+            reason,
+        };
+
+        let mut members = Vec::new();
+        let mut member_offsets = Vec::new();
+        let mut index = None;
+        let mut long_names: &[u8] = &[];
+
+        let mut offset = ARCHIVE_MAGIC.len();
+        while offset < buffer.len() {
+            let header = buffer
+                .get(offset..offset + HEADER_SIZE)
+                .ok_or_else(|| error(offset, "truncated member header"))?;
+            if &header[58..60] != HEADER_END {
+                return Err(error(offset, "bad member header terminator"));
+            }
+            let size = header_field(&header[48..58])
+                .parse::<usize>()
+                .map_err(|_| error(offset, "bad member size"))?;
+            let data_offset = offset + HEADER_SIZE;
+            let data = data_offset
+                .checked_add(size)
+                .and_then(|end| buffer.get(data_offset..end))
+                .ok_or_else(|| error(offset, "member extends past end of file"))?;
+            let raw_name = header_field(&header[0..16]);
+
+            if raw_name == SYMBOL_INDEX_NAME {
+                index = Some(
+                    parse_symbol_index(data).ok_or_else(|| error(offset, "bad symbol index"))?,
+                );
+            } else if raw_name == LONG_NAMES_NAME {
+                long_names = data;
+            } else if !raw_name.starts_with("__.SYMDEF") {
+                let (name, data) = if let Some(len) = raw_name.strip_prefix(BSD_NAME_PREFIX) {
+                    let len = len
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&len| len <= data.len())
+                        .ok_or_else(|| error(offset, "bad member name"))?;
+                    let name = String::from_utf8_lossy(&data[..len]);
+                    (name.trim_end_matches('\0').to_owned(), &data[len..])
+                } else if let Some(name_offset) = raw_name
+                    .strip_prefix('/')
+                    .and_then(|rest| rest.parse::<usize>().ok())
+                {
+                    let name = long_names
+                        .get(name_offset..)
+                        .and_then(|names| {
+                            let end = names.iter().position(|&byte| byte == b'\n')?;
+                            Some(String::from_utf8_lossy(&names[..end]).into_owned())
+                        })
+                        .ok_or_else(|| error(offset, "bad long member name"))?;
+                    (name.trim_end_matches('/').to_owned(), data)
+                } else {
+                    (raw_name.trim_end_matches('/').to_owned(), data)
+                };
+                members.push(Member {
+                    name,
+                    data: data.to_vec(),
+                });
+                member_offsets.push(offset);
+            }
+
+            // Member data is padded to an even offset.
+            offset = data_offset + size + (size & 1);
+        }
+
+        let symbols = match index {
+            Some(index) => Some(
+                index
+                    .into_iter()
+                    .map(|(name, header_offset)| {
+                        member_offsets
+                            .iter()
+                            .position(|&offset| offset == header_offset)
+                            .map(|member| (name, member))
+                            .ok_or_else(|| error(header_offset, "symbol index names no member"))
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            None => None,
+        };
+
+        Ok(Archive { members, symbols })
+    }
+
+    /// Serializes the archive with a symbol index listing the symbols each
+    /// link32 object member defines. Members that are not link32 objects are
+    /// stored but left out of the index.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, LinkError> {
+        let mut index = Vec::new();
+        for (i, member) in self.members.iter().enumerate() {
+            let path = PathBuf::from(&member.name);
+            match ObjectFile::parse(&path, &member.data, false) {
+                Ok(obj_file) => {
+                    index.extend(obj_file.exported_names().map(|name| (name.clone(), i)))
+                }
+                Err(LinkError::BadMagic { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut long_names = Vec::new();
+        let header_names: Vec<String> = self
+            .members
+            .iter()
+            .map(|member| {
+                if member.name.len() < 16 && !member.name.contains(['/', ' ']) {
+                    format!("{}/", member.name)
+                } else {
+                    let name = format!("/{}", long_names.len());
+                    long_names.extend_from_slice(member.name.as_bytes());
+                    long_names.extend_from_slice(b"/\n");
+                    name
+                }
+            })
+            .collect();
+
+        let index_size =
+            4 + 4 * index.len() + index.iter().map(|(name, _)| name.len() + 1).sum::<usize>();
+        let mut offset = ARCHIVE_MAGIC.len();
+        if !index.is_empty() {
+            offset += HEADER_SIZE + padded(index_size);
+        }
+        if !long_names.is_empty() {
+            offset += HEADER_SIZE + padded(long_names.len());
+        }
+        let mut member_offsets = Vec::new();
+        for member in &self.members {
+            member_offsets.push(offset);
+            offset += HEADER_SIZE + padded(member.data.len());
+        }
+
+        let mut out = ARCHIVE_MAGIC.to_vec();
+        if !index.is_empty() {
+            let mut data = Vec::with_capacity(index_size);
+            data.extend_from_slice(&(index.len() as u32).to_be_bytes());
+            for (_, member) in &index {
+                data.extend_from_slice(&(member_offsets[*member] as u32).to_be_bytes());
+            }
+            for (name, _) in &index {
+                data.extend_from_slice(name.as_bytes());
+                data.push(0);
+            }
+            write_member(&mut out, SYMBOL_INDEX_NAME, &data);
+        }
+        if !long_names.is_empty() {
+            write_member(&mut out, LONG_NAMES_NAME, &long_names);
+        }
+        for (member, name) in self.members.iter().zip(&header_names) {
+            write_member(&mut out, name, &member.data);
+        }
+
+        Ok(out)
+    }
+}
+
+fn padded(size: usize) -> usize {
+    size + (size & 1)
+}
+
+/// Appends a member header and data. Timestamps and owners are written as
+/// zero so archives are reproducible.
+fn write_member(out: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let header = format!(
+        "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}",
+        name,
+        0,
+        0,
+        0,
+        644,
+        data.len()
+    );
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(HEADER_END);
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(b'\n');
+    }
+}
+
+/// The path used for a member in diagnostics, like `libc.a(puts.o)`.
+pub(crate) fn member_path(archive: &Path, member: &str) -> PathBuf {
+    PathBuf::from(format!("{}({})", archive.display(), member))
+}
+
+fn header_field(field: &[u8]) -> &str {
+    std::str::from_utf8(field)
+        .unwrap_or("")
+        .trim_end_matches(' ')
+}
+
+/// The GNU symbol index: a big-endian count, that many big-endian offsets of
+/// member headers, then that many null-terminated symbol names.
+fn parse_symbol_index(data: &[u8]) -> Option<Vec<(String, usize)>> {
+    let read_u32 = |offset: usize| {
+        let bytes = data.get(offset..offset + 4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    };
+
+    let count = read_u32(0)?;
+    let mut names = data
+        .get(4 + count.checked_mul(4)?..)?
+        .split(|&byte| byte == 0);
+    (0..count)
+        .map(|i| {
+            let offset = read_u32(4 + i * 4)?;
+            let name = String::from_utf8(names.next()?.to_vec()).ok()?;
+            Some((name, offset))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::tests::{object_bytes, Contents};
+
+    #[test]
+    fn round_trips_members_long_names_and_symbol_index() {
+        let member = |name: &str, data: Vec<u8>| Member {
+            name: name.to_owned(),
+            data,
+        };
+        let archive = Archive {
+            members: vec![
+                member(
+                    "a.o",
+                    object_bytes(
+                        &[(".text", Contents::Data(b"\xc3"))],
+                        &[("alpha", 0, 0), ("beta", 0, 1)],
+                        &[],
+                    ),
+                ),
+                member("README", b"odd sized".to_vec()),
+                member(
+                    "a_rather_long_member_name.o",
+                    object_bytes(
+                        &[(".text", Contents::Data(b"\xc3\xc3"))],
+                        &[("gamma", 0, 0)],
+                        &[],
+                    ),
+                ),
+                member("name with spaces.o", Vec::new()),
+            ],
+            symbols: None,
+        };
+
+        let bytes = archive.to_bytes().unwrap();
+        let parsed = Archive::parse(Path::new("lib.a"), &bytes).unwrap();
+
+        let names: Vec<_> = parsed.members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "a.o",
+                "README",
+                "a_rather_long_member_name.o",
+                "name with spaces.o"
+            ]
+        );
+        for (parsed, original) in parsed.members.iter().zip(&archive.members) {
+            assert_eq!(parsed.data, original.data);
+        }
+        assert_eq!(
+            parsed.symbols.unwrap(),
+            [
+                ("alpha".to_owned(), 0),
+                ("beta".to_owned(), 0),
+                ("gamma".to_owned(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_member() {
+        let mut archive = Archive::new();
+        archive.members.push(Member {
+            name: "a.o".to_owned(),
+            data: vec![0; 10],
+        });
+        let bytes = archive.to_bytes().unwrap();
+        assert!(Archive::parse(Path::new("lib.a"), &bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,431 @@
+use crate::image::{Image, OutputSection};
+use crate::object::{SectionKind, ABSOLUTE_SECTION_NAME};
+
+const EHDR_SIZE: u32 = 52;
+const PHDR_SIZE: u32 = 32;
+const SHDR_SIZE: u32 = 40;
+const SYM_SIZE: u32 = 16;
+pub(crate) const PAGE_SIZE: u32 = 0x1000;
+
+const ET_EXEC: u16 = 2;
+const EM_386: u16 = 3;
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+const SHF_WRITE: u32 = 1;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+const SHN_ABS: u16 = 0xfff1;
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_FILE: u8 = 4;
+
+#[derive(Default)]
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u32,
+    address: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+    align: u32,
+    entry_size: u32,
+}
+
+/// Null-terminated names, addressed by their offset into the table.
+struct StringTable {
+    bytes: Vec<u8>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        StringTable { bytes: vec![0] }
+    }
+
+    fn add(&mut self, name: &str) -> u32 {
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend_from_slice(name.as_bytes());
+        self.bytes.push(0);
+        offset
+    }
+}
+
+/// Output sections loaded together by one `PT_LOAD` program header.
+struct Segment<'a> {
+    sections: Vec<&'a OutputSection>,
+    offset: u32,
+}
+
+impl Segment<'_> {
+    fn address(&self) -> u32 {
+        self.sections[0].address
+    }
+
+    fn size(&self) -> u32 {
+        self.sections[self.sections.len() - 1].end() - self.address()
+    }
+
+    /// The size without any trailing `NoBits` sections, which the loader
+    /// zero-fills instead of reading from the file.
+    fn file_size(&self) -> u32 {
+        self.sections
+            .iter()
+            .rev()
+            .find(|section| section.kind == SectionKind::ProgBits)
+            .map_or(0, |section| section.end() - self.address())
+    }
+
+    fn flags(&self) -> u32 {
+        self.sections
+            .iter()
+            .fold(PF_R, |flags, section| flags | segment_flags(section))
+    }
+}
+
+fn segment_flags(section: &OutputSection) -> u32 {
+    let mut flags = PF_R;
+    if section.is_code() {
+        flags |= PF_X;
+    }
+    if section.is_writable() {
+        flags |= PF_W;
+    }
+    flags
+}
+
+fn push_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Wraps the image in an i386 ELF executable with a section header and
+/// symbol table for debuggers. Sections with the same permissions less than
+/// a page apart share a loadable segment. Sections sharing a page always
+/// do, since the loader cannot give one page two sets of permissions.
+pub(crate) fn write_elf32(image: &Image) -> Vec<u8> {
+    let mut segments: Vec<Segment> = Vec::new();
+    for section in &image.sections {
+        let joins = |segment: &Segment| {
+            let end = segment.address() + segment.size();
+            let Some(gap) = section.address.checked_sub(end) else {
+                return false;
+            };
+            let shares_page = section.address / PAGE_SIZE == end.saturating_sub(1) / PAGE_SIZE;
+            shares_page || (gap < PAGE_SIZE && segment.flags() == segment_flags(section))
+        };
+        match segments.last_mut() {
+            Some(segment) if joins(segment) => segment.sections.push(section),
+            _ => segments.push(Segment {
+                sections: vec![section],
+                offset: 0,
+            }),
+        }
+    }
+
+    // The loader maps whole pages, so each segment's file offset must be
+    // congruent to its address modulo the page size.
+    let mut offset = EHDR_SIZE + PHDR_SIZE * segments.len() as u32;
+    for segment in &mut segments {
+        segment.offset = offset + (segment.address().wrapping_sub(offset) % PAGE_SIZE);
+        offset = segment.offset + segment.file_size();
+    }
+
+    let mut shstrtab = StringTable::new();
+    let mut sections = vec![SectionHeader::default()];
+    for segment in &segments {
+        for section in &segment.sections {
+            let mut flags = SHF_ALLOC;
+            if section.is_code() {
+                flags |= SHF_EXECINSTR;
+            }
+            if section.is_writable() {
+                flags |= SHF_WRITE;
+            }
+            sections.push(SectionHeader {
+                name: shstrtab.add(&section.name),
+                kind: match section.kind {
+                    SectionKind::ProgBits => SHT_PROGBITS,
+                    SectionKind::NoBits => SHT_NOBITS,
+                },
+                flags,
+                address: section.address,
+                offset: segment.offset + (section.address - segment.address()),
+                size: section.size,
+                align: section.align,
+                ..SectionHeader::default()
+            });
+        }
+    }
+
+    let section_index = |address: u32| {
+        image
+            .sections
+            .iter()
+            .position(|section| (section.address..section.end()).contains(&address))
+            .or_else(|| {
+                image
+                    .sections
+                    .iter()
+                    .position(|section| section.end() == address)
+            })
+            .map_or(SHN_ABS, |index| index as u16 + 1)
+    };
+    let mut strtab = StringTable::new();
+    let mut symtab = vec![0; SYM_SIZE as usize];
+    let mut push_symbol = |name: &str, address: u32, info: u8, section_index: u16| {
+        push_u32(&mut symtab, strtab.add(name));
+        push_u32(&mut symtab, address);
+        push_u32(&mut symtab, 0);
+        symtab.push(info);
+        symtab.push(0);
+        push_u16(&mut symtab, section_index);
+    };
+
+    // Local symbols come first, each object's after an `STT_FILE` symbol
+    // naming it, so tools can tell apart locals of the same name.
+    let mut symbol_count = 1;
+    let mut previous_path = None;
+    for symbol in &image.local_symbols {
+        let path = &symbol.location.path;
+        if previous_path != Some(path) {
+            let file = path.file_name().unwrap_or_default();
+            push_symbol(
+                &file.to_string_lossy(),
+                0,
+                STB_LOCAL << 4 | STT_FILE,
+                SHN_ABS,
+            );
+            symbol_count += 1;
+            previous_path = Some(path);
+        }
+        let address = symbol.location.address;
+        let index = if symbol.location.section == ABSOLUTE_SECTION_NAME {
+            SHN_ABS
+        } else {
+            section_index(address)
+        };
+        push_symbol(&symbol.name, address, STB_LOCAL << 4, index);
+        symbol_count += 1;
+    }
+    let first_global = symbol_count;
+
+    let mut symbols: Vec<(&String, &u32)> = image.symbols.iter().collect();
+    symbols.sort_by(|a, b| a.1.cmp(b.1).then(a.0.cmp(b.0)));
+    for (name, &address) in symbols {
+        let index = if image.absolute_symbols.contains(name) {
+            SHN_ABS
+        } else {
+            section_index(address)
+        };
+        push_symbol(name, address, STB_GLOBAL << 4, index);
+    }
+
+    let symtab_offset = offset.next_multiple_of(4);
+    let strtab_offset = symtab_offset + symtab.len() as u32;
+    let strtab_index = sections.len() as u32 + 1;
+    sections.push(SectionHeader {
+        name: shstrtab.add(".symtab"),
+        kind: SHT_SYMTAB,
+        offset: symtab_offset,
+        size: symtab.len() as u32,
+        link: strtab_index,
+        info: first_global,
+        align: 4,
+        entry_size: SYM_SIZE,
+        ..SectionHeader::default()
+    });
+    sections.push(SectionHeader {
+        name: shstrtab.add(".strtab"),
+        kind: SHT_STRTAB,
+        offset: strtab_offset,
+        size: strtab.bytes.len() as u32,
+        align: 1,
+        ..SectionHeader::default()
+    });
+    let shstrtab_index = sections.len() as u16;
+    let shstrtab_name = shstrtab.add(".shstrtab");
+    let shstrtab_offset = strtab_offset + strtab.bytes.len() as u32;
+    sections.push(SectionHeader {
+        name: shstrtab_name,
+        kind: SHT_STRTAB,
+        offset: shstrtab_offset,
+        size: shstrtab.bytes.len() as u32,
+        align: 1,
+        ..SectionHeader::default()
+    });
+    let section_headers_offset =
+        (shstrtab_offset + shstrtab.bytes.len() as u32).next_multiple_of(4);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(b"\x7fELF");
+    // 32-bit, little-endian, ELF version 1, System V ABI.
+    out.extend_from_slice(&[1, 1, 1, 0]);
+    out.resize(16, 0);
+    push_u16(&mut out, ET_EXEC);
+    push_u16(&mut out, EM_386);
+    push_u32(&mut out, 1);
+    push_u32(&mut out, image.entry);
+    push_u32(&mut out, EHDR_SIZE);
+    push_u32(&mut out, section_headers_offset);
+    push_u32(&mut out, 0);
+    push_u16(&mut out, EHDR_SIZE as u16);
+    push_u16(&mut out, PHDR_SIZE as u16);
+    push_u16(&mut out, segments.len() as u16);
+    push_u16(&mut out, SHDR_SIZE as u16);
+    push_u16(&mut out, sections.len() as u16);
+    push_u16(&mut out, shstrtab_index);
+
+    for segment in &segments {
+        push_u32(&mut out, PT_LOAD);
+        push_u32(&mut out, segment.offset);
+        push_u32(&mut out, segment.address());
+        push_u32(&mut out, segment.address());
+        push_u32(&mut out, segment.file_size());
+        push_u32(&mut out, segment.size());
+        push_u32(&mut out, segment.flags());
+        push_u32(&mut out, PAGE_SIZE);
+    }
+
+    for segment in &segments {
+        for section in &segment.sections {
+            out.resize(
+                (segment.offset + section.address - segment.address()) as usize,
+                0,
+            );
+            out.extend_from_slice(&section.data);
+        }
+    }
+    out.resize(symtab_offset as usize, 0);
+    out.extend_from_slice(&symtab);
+    out.extend_from_slice(&strtab.bytes);
+    out.extend_from_slice(&shstrtab.bytes);
+    out.resize(section_headers_offset as usize, 0);
+
+    for section in &sections {
+        push_u32(&mut out, section.name);
+        push_u32(&mut out, section.kind);
+        push_u32(&mut out, section.flags);
+        push_u32(&mut out, section.address);
+        push_u32(&mut out, section.offset);
+        push_u32(&mut out, section.size);
+        push_u32(&mut out, section.link);
+        push_u32(&mut out, section.info);
+        push_u32(&mut out, section.align);
+        push_u32(&mut out, section.entry_size);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::tests::{object_bytes, Contents};
+    use crate::{Linker, OutputFormat};
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn name_at(bytes: &[u8], offset: usize) -> &str {
+        let end = offset + bytes[offset..].iter().position(|&b| b == 0).unwrap();
+        std::str::from_utf8(&bytes[offset..end]).unwrap()
+    }
+
+    #[test]
+    fn writes_headers_segments_and_symbols() {
+        let main = object_bytes(
+            &[
+                (".text", Contents::Data(b"\x90\xa1\0\0\0\0\xc3")),
+                (".data", Contents::Data(b"dddd")),
+                (".bss", Contents::Zeroed(8)),
+            ],
+            &[("_start", 0, 1), ("value", 1, 0)],
+            &[("value", 0, 2)],
+        );
+        let image = Linker::new()
+            .format(OutputFormat::Elf32)
+            .add_bytes("main.o", main)
+            .link()
+            .unwrap();
+        let bytes = image.to_bytes();
+
+        assert_eq!(bytes[..7], *b"\x7fELF\x01\x01\x01");
+        assert_eq!(u16_at(&bytes, 16), ET_EXEC);
+        assert_eq!(u16_at(&bytes, 18), EM_386);
+        assert_eq!(u32_at(&bytes, 24), 0x0804_8001);
+        assert_eq!(u32_at(&bytes, 28), EHDR_SIZE);
+        assert_eq!(u16_at(&bytes, 44), 2);
+
+        // Code maps read and execute, data and .bss read and write, each on
+        // its own pages.
+        let segment = |i: u32| {
+            let offset = (EHDR_SIZE + i * PHDR_SIZE) as usize;
+            let field = |n: usize| u32_at(&bytes, offset + n * 4);
+            (field(0), field(1), field(2), field(4), field(5), field(6))
+        };
+        let (kind, offset, address, file_size, memory_size, flags) = segment(0);
+        assert_eq!(
+            (kind, address, file_size, memory_size),
+            (PT_LOAD, 0x0804_8000, 7, 7)
+        );
+        assert_eq!(flags, PF_R | PF_X);
+        assert_eq!(offset % PAGE_SIZE, address % PAGE_SIZE);
+        assert_eq!(
+            bytes[offset as usize..][..7],
+            *b"\x90\xa1\0\x90\x04\x08\xc3"
+        );
+        let (kind, offset, address, file_size, memory_size, flags) = segment(1);
+        assert_eq!(
+            (kind, address, file_size, memory_size),
+            (PT_LOAD, 0x0804_9000, 4, 12)
+        );
+        assert_eq!(flags, PF_R | PF_W);
+        assert_eq!(offset % PAGE_SIZE, address % PAGE_SIZE);
+
+        // Find the symbol table through the section headers.
+        let section_headers = u32_at(&bytes, 32) as usize;
+        let section_count = u16_at(&bytes, 48) as usize;
+        let header = |i: usize| {
+            let offset = section_headers + i * SHDR_SIZE as usize;
+            (0..10)
+                .map(|n| u32_at(&bytes, offset + n * 4))
+                .collect::<Vec<_>>()
+        };
+        let symtab = (0..section_count)
+            .map(header)
+            .find(|header| header[1] == SHT_SYMTAB)
+            .unwrap();
+        let strtab = header(symtab[6] as usize);
+        let symbols: Vec<(&str, u32, u8, u16)> = (0..symtab[5] / SYM_SIZE)
+            .map(|i| {
+                let offset = (symtab[4] + i * SYM_SIZE) as usize;
+                let name = name_at(&bytes, (strtab[4] + u32_at(&bytes, offset)) as usize);
+                (
+                    name,
+                    u32_at(&bytes, offset + 4),
+                    bytes[offset + 12],
+                    u16_at(&bytes, offset + 14),
+                )
+            })
+            .collect();
+        assert!(symbols.contains(&("_start", 0x0804_8001, STB_GLOBAL << 4, 1)));
+        assert!(symbols.contains(&("value", 0x0804_9000, STB_GLOBAL << 4, 2)));
+        assert!(symbols.contains(&("__bss_start", 0x0804_9004, STB_GLOBAL << 4, 3)));
+        assert_eq!(symbols[0], ("", 0, 0, 0));
+    }
+}
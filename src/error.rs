@@ -0,0 +1,439 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong while reading inputs, linking or writing the
+/// output. Offsets are byte offsets into the file named by `path`.
+#[derive(Debug)]
+pub enum LinkError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    BadMagic {
+        path: PathBuf,
+    },
+    UnsupportedVersion {
+        path: PathBuf,
+        version: u32,
+    },
+    TruncatedHeader {
+        path: PathBuf,
+        len: usize,
+    },
+    SymbolTableOutOfBounds {
+        path: PathBuf,
+        offset: usize,
+    },
+    RelocationTableOutOfBounds {
+        path: PathBuf,
+        offset: usize,
+    },
+    SectionTableOutOfBounds {
+        path: PathBuf,
+        offset: usize,
+    },
+    SectionOutOfBounds {
+        path: PathBuf,
+        section: String,
+        offset: usize,
+    },
+    UnknownSectionKind {
+        path: PathBuf,
+        offset: usize,
+        kind: u8,
+    },
+    InvalidAlignment {
+        path: PathBuf,
+        section: String,
+        align: u32,
+    },
+    UnknownBinding {
+        path: PathBuf,
+        offset: usize,
+        binding: u8,
+    },
+    InvalidCommonAlignment {
+        path: PathBuf,
+        symbol: String,
+        align: u32,
+    },
+    InvalidSectionIndex {
+        path: PathBuf,
+        symbol: String,
+        index: u32,
+    },
+    InvalidUtf8 {
+        path: PathBuf,
+        offset: usize,
+    },
+    UnknownRelocationKind {
+        path: PathBuf,
+        offset: usize,
+        kind: u8,
+    },
+    RelocationOutOfRange {
+        path: PathBuf,
+        section: String,
+        symbol: String,
+        offset: u32,
+    },
+    SymbolOutOfRange {
+        path: PathBuf,
+        section: String,
+        symbol: String,
+        offset: u32,
+    },
+    SectionTooLarge {
+        path: PathBuf,
+        section: String,
+        size: u32,
+    },
+    LibraryNotFound {
+        name: String,
+        search_paths: Vec<PathBuf>,
+    },
+    InvalidArchive {
+        path: PathBuf,
+        offset: usize,
+        reason: &'static str,
+    },
+    DuplicateSymbol {
+        name: String,
+        first: Box<Location>,
+        second: Box<Location>,
+    },
+    CommonSizeMismatch {
+        name: String,
+        common_size: u32,
+        path: PathBuf,
+        size: u32,
+    },
+    UndefinedSymbols(Vec<UndefinedSymbol>),
+    UndefinedEntry {
+        name: String,
+    },
+    ScriptSyntax {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    InvalidExpression {
+        expression: String,
+        reason: String,
+    },
+    RegionOverflow {
+        region: String,
+        section: String,
+        overflow: u64,
+    },
+    SectionOverlap {
+        first: String,
+        second: String,
+    },
+    EntryStubNotFirst {
+        section: String,
+        first: String,
+    },
+    AddressOverflow {
+        section: String,
+    },
+}
+
+/// A place in the linked image: `offset` bytes into `section` of the object
+/// at `path`, which ended up at `address` in the output.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub path: PathBuf,
+    pub section: String,
+    pub offset: u32,
+    pub address: u32,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:({}+{:#x}) ({:#x})",
+            self.path.display(),
+            self.section,
+            self.offset,
+            self.address
+        )
+    }
+}
+
+/// A symbol referenced by relocations but defined by no input, with every
+/// place that references it.
+#[derive(Debug)]
+pub struct UndefinedSymbol {
+    pub name: String,
+    pub references: Vec<Location>,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            LinkError::BadMagic { path } => write!(
+                f,
+                "{}: not a link32 object file (missing '{}' signature)",
+                path.display(),
+                String::from_utf8_lossy(&crate::object::MAGIC)
+            ),
+            LinkError::UnsupportedVersion { path, version } => write!(
+                f,
+                "{}: unsupported object format version {} (expected 1 to {})",
+                path.display(),
+                version,
+                crate::object::FORMAT_VERSION
+            ),
+            LinkError::TruncatedHeader { path, len } => {
+                write!(f, "{}: truncated header ({} bytes)", path.display(), len)
+            }
+            LinkError::SymbolTableOutOfBounds { path, offset } => write!(
+                f,
+                "{}+{:#x}: symbol table extends past end of file",
+                path.display(),
+                offset
+            ),
+            LinkError::RelocationTableOutOfBounds { path, offset } => write!(
+                f,
+                "{}+{:#x}: relocation table extends past end of file",
+                path.display(),
+                offset
+            ),
+            LinkError::SectionTableOutOfBounds { path, offset } => write!(
+                f,
+                "{}+{:#x}: section table extends past end of file",
+                path.display(),
+                offset
+            ),
+            LinkError::SectionOutOfBounds {
+                path,
+                section,
+                offset,
+            } => write!(
+                f,
+                "{}+{:#x}: data of section '{}' extends past end of file",
+                path.display(),
+                offset,
+                section
+            ),
+            LinkError::UnknownSectionKind { path, offset, kind } => write!(
+                f,
+                "{}+{:#x}: unknown section kind {}",
+                path.display(),
+                offset,
+                kind
+            ),
+            LinkError::InvalidAlignment {
+                path,
+                section,
+                align,
+            } => write!(
+                f,
+                "{}: section '{}' has alignment {}, which is not a power of two",
+                path.display(),
+                section,
+                align
+            ),
+            LinkError::UnknownBinding {
+                path,
+                offset,
+                binding,
+            } => write!(
+                f,
+                "{}+{:#x}: unknown symbol binding {}",
+                path.display(),
+                offset,
+                binding
+            ),
+            LinkError::InvalidCommonAlignment {
+                path,
+                symbol,
+                align,
+            } => write!(
+                f,
+                "{}: common symbol '{}' has alignment {}, which is not a power of two",
+                path.display(),
+                symbol,
+                align
+            ),
+            LinkError::InvalidSectionIndex {
+                path,
+                symbol,
+                index,
+            } => write!(
+                f,
+                "{}: '{}' refers to section {}, which does not exist",
+                path.display(),
+                symbol,
+                index
+            ),
+            LinkError::InvalidUtf8 { path, offset } => write!(
+                f,
+                "{}+{:#x}: symbol name is not valid UTF-8",
+                path.display(),
+                offset
+            ),
+            LinkError::UnknownRelocationKind { path, offset, kind } => write!(
+                f,
+                "{}+{:#x}: unknown relocation kind {}",
+                path.display(),
+                offset,
+                kind
+            ),
+            LinkError::RelocationOutOfRange {
+                path,
+                section,
+                symbol,
+                offset,
+            } => write!(
+                f,
+                "{}:({}+{:#x}): relocation against '{}' is outside the section",
+                path.display(),
+                section,
+                offset,
+                symbol
+            ),
+            LinkError::SymbolOutOfRange {
+                path,
+                section,
+                symbol,
+                offset,
+            } => write!(
+                f,
+                "{}:({}+{:#x}): symbol '{}' is outside the section",
+                path.display(),
+                section,
+                offset,
+                symbol
+            ),
+            LinkError::SectionTooLarge {
+                path,
+                section,
+                size,
+            } => write!(
+                f,
+                "{}: section '{}' of {:#x} bytes does not fit in the 32-bit address space",
+                path.display(),
+                section,
+                size
+            ),
+            LinkError::LibraryNotFound { name, search_paths } => {
+                write!(f, "cannot find library 'lib{}.a'", name)?;
+                if search_paths.is_empty() {
+                    write!(f, " (no library search paths given)")
+                } else {
+                    write!(f, " in:")?;
+                    for dir in search_paths {
+                        write!(f, "\n  {}", dir.display())?;
+                    }
+                    Ok(())
+                }
+            }
+            LinkError::InvalidArchive {
+                path,
+                offset,
+                reason,
+            } => write!(
+                f,
+                "{}+{:#x}: malformed archive: {}",
+                path.display(),
+                offset,
+                reason
+            ),
+            LinkError::DuplicateSymbol {
+                name,
+                first,
+                second,
+            } => write!(
+                f,
+                "duplicate symbol '{}': defined in {} and {}",
+                name, first, second
+            ),
+            LinkError::CommonSizeMismatch {
+                name,
+                common_size,
+                path,
+                size,
+            } => write!(
+                f,
+                "common symbol '{}' of {} bytes is defined in {} with only {} bytes",
+                name,
+                common_size,
+                path.display(),
+                size
+            ),
+            LinkError::UndefinedSymbols(symbols) => {
+                for (i, symbol) in symbols.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(
+                        f,
+                        "undefined symbol '{}' ({} reference{})",
+                        symbol.name,
+                        symbol.references.len(),
+                        if symbol.references.len() == 1 {
+                            ""
+                        } else {
+                            "s"
+                        }
+                    )?;
+                    for reference in &symbol.references {
+                        write!(
+                            f,
+                            "\n  {}: undefined reference to '{}'",
+                            reference, symbol.name
+                        )?;
+                    }
+                }
+                Ok(())
+            }
+            LinkError::UndefinedEntry { name } => {
+                write!(f, "entry symbol '{}' is not defined", name)
+            }
+            LinkError::ScriptSyntax {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            LinkError::InvalidExpression { expression, reason } => {
+                write!(f, "cannot evaluate '{}': {}", expression, reason)
+            }
+            LinkError::RegionOverflow {
+                region,
+                section,
+                overflow,
+            } => write!(
+                f,
+                "section '{}' overflows memory region '{}' by {} bytes",
+                section, region, overflow
+            ),
+            LinkError::SectionOverlap { first, second } => {
+                write!(f, "section '{}' overlaps section '{}'", first, second)
+            }
+            LinkError::AddressOverflow { section } => write!(
+                f,
+                "section '{}' extends past the end of the 32-bit address space",
+                section
+            ),
+            LinkError::EntryStubNotFirst { section, first } => write!(
+                f,
+                "the entry stub must start the image, but section '{}' is placed below '{}'",
+                first, section
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LinkError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use crate::image::DiscardedSection;
+use crate::layout::output_section_of;
+use crate::object::{Binding, ObjectFile, Relocation, Symbol};
+use crate::script::Script;
+
+/// Drops every input section that cannot be reached from the sections
+/// defining `roots` or kept by the script, following relocations to the
+/// sections defining their symbols, local definitions first. A reference to
+/// `__start_<name>` or `__stop_<name>` that no input defines reaches every
+/// section bound for output section `<name>`. Symbols and relocations in
+/// dropped sections go with them, as do objects left with neither sections
+/// nor absolute symbols.
+pub(crate) fn collect_garbage(
+    objects: Vec<ObjectFile>,
+    roots: &[&str],
+    script: &Script,
+) -> (Vec<ObjectFile>, Vec<DiscardedSection>) {
+    // The object and section defining each symbol; absolute symbols have
+    // no section to keep.
+    let section_of = |symbol: &Symbol| (!symbol.is_absolute()).then_some(symbol.section as usize);
+    let mut definitions = HashMap::<&str, (usize, Option<usize>, Binding)>::new();
+    for (i, obj_file) in objects.iter().enumerate() {
+        for symbol in &obj_file.symbols {
+            let definition = (i, section_of(symbol), symbol.binding);
+            let existing = definitions.entry(&symbol.name).or_insert(definition);
+            if existing.2 == Binding::Weak && symbol.binding == Binding::Global {
+                *existing = definition;
+            }
+        }
+    }
+
+    let mut live: Vec<Vec<bool>> = objects
+        .iter()
+        .map(|obj_file| vec![false; obj_file.sections.len()])
+        .collect();
+    // `__start_<name>` and `__stop_<name>`, unless an input defines them,
+    // reach every input section placed in output section `<name>`.
+    let outputs: Vec<Vec<&str>> = objects
+        .iter()
+        .map(|obj_file| {
+            obj_file
+                .sections
+                .iter()
+                .map(|section| output_section_of(script, &obj_file.path, &section.name))
+                .collect()
+        })
+        .collect();
+    let targets = |name: &str| -> Vec<(usize, usize)> {
+        if let Some(&(i, j, _)) = definitions.get(name) {
+            return j.map(|j| (i, j)).into_iter().collect();
+        }
+        let Some(output) = name
+            .strip_prefix("__start_")
+            .or_else(|| name.strip_prefix("__stop_"))
+        else {
+            return Vec::new();
+        };
+        let mut targets = Vec::new();
+        for (i, names) in outputs.iter().enumerate() {
+            for (j, &name) in names.iter().enumerate() {
+                if name == output {
+                    targets.push((i, j));
+                }
+            }
+        }
+        targets
+    };
+
+    let mut worklist: Vec<(usize, usize)> = roots.iter().flat_map(|name| targets(name)).collect();
+    for (i, obj_file) in objects.iter().enumerate() {
+        for (j, section) in obj_file.sections.iter().enumerate() {
+            if script.keeps(&obj_file.path, &section.name) {
+                worklist.push((i, j));
+            }
+        }
+    }
+
+    while let Some((i, j)) = worklist.pop() {
+        if live[i][j] {
+            continue;
+        }
+        live[i][j] = true;
+        let obj_file = &objects[i];
+        for relocation in &obj_file.relocations {
+            if relocation.section as usize != j {
+                continue;
+            }
+            let local = obj_file
+                .local_symbols
+                .iter()
+                .find(|symbol| symbol.name == relocation.symbol);
+            match local {
+                Some(symbol) => worklist.extend(section_of(symbol).map(|j| (i, j))),
+                None => worklist.extend(targets(&relocation.symbol)),
+            }
+        }
+    }
+
+    let mut kept = Vec::new();
+    let mut discarded = Vec::new();
+    for (obj_file, live) in objects.into_iter().zip(live) {
+        let mut new_index = vec![None; obj_file.sections.len()];
+        let mut sections = Vec::new();
+        for (j, section) in obj_file.sections.into_iter().enumerate() {
+            if live[j] {
+                new_index[j] = Some(sections.len() as u32);
+                sections.push(section);
+            } else {
+                discarded.push(DiscardedSection {
+                    path: obj_file.path.clone(),
+                    name: section.name,
+                    size: section.size,
+                });
+            }
+        }
+        let has_absolute = obj_file
+            .symbols
+            .iter()
+            .chain(&obj_file.local_symbols)
+            .any(Symbol::is_absolute);
+        if sections.is_empty() && !has_absolute {
+            continue;
+        }
+
+        let remap = |symbols: Vec<Symbol>| {
+            symbols
+                .into_iter()
+                .filter_map(|symbol| {
+                    if symbol.is_absolute() {
+                        return Some(symbol);
+                    }
+                    Some(Symbol {
+                        section: new_index[symbol.section as usize]?,
+                        ..symbol
+                    })
+                })
+                .collect()
+        };
+        let symbols = remap(obj_file.symbols);
+        let local_symbols = remap(obj_file.local_symbols);
+        let relocations = obj_file
+            .relocations
+            .into_iter()
+            .filter_map(|relocation| {
+                Some(Relocation {
+                    section: new_index[relocation.section as usize]?,
+                    ..relocation
+                })
+            })
+            .collect();
+        kept.push(ObjectFile {
+            path: obj_file.path,
+            sections,
+            symbols,
+            local_symbols,
+            common_symbols: obj_file.common_symbols,
+            weak_references: obj_file.weak_references,
+            relocations,
+        });
+    }
+
+    (kept, discarded)
+}
@@ -0,0 +1,205 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::elf::write_elf32;
+use crate::error::{LinkError, Location};
+use crate::map::write_map;
+use crate::object::{RelocationKind, SectionKind};
+
+/// The file format `Image::to_bytes` emits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The linked code as a raw blob, to be loaded at the base address.
+    #[default]
+    Flat,
+    /// An i386 ELF executable with a symbol table, runnable on Linux.
+    Elf32,
+}
+
+impl OutputFormat {
+    /// The base address used when none is given: zero for flat binaries,
+    /// and the traditional i386 executable address for ELF.
+    pub fn default_base_address(self) -> u32 {
+        match self {
+            OutputFormat::Flat => 0,
+            OutputFormat::Elf32 => 0x0804_8000,
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "binary" | "flat" => Ok(OutputFormat::Flat),
+            "elf32" => Ok(OutputFormat::Elf32),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+/// The like-named sections of every input, concatenated and placed at
+/// `address`. A `NoBits` output section has no `data`, only a `size`.
+/// `align` is the largest alignment of its input sections.
+pub struct OutputSection {
+    pub name: String,
+    pub kind: SectionKind,
+    pub align: u32,
+    pub address: u32,
+    pub data: Vec<u8>,
+    pub size: u32,
+}
+
+impl OutputSection {
+    /// The address just past the section. Layout fails rather than place a
+    /// section that would end past the 32-bit address space.
+    pub fn end(&self) -> u32 {
+        self.address + self.size
+    }
+
+    pub fn is_code(&self) -> bool {
+        self.name == ".text"
+    }
+
+    pub fn is_writable(&self) -> bool {
+        !self.is_code() && self.name != ".rodata"
+    }
+}
+
+/// Where section `name` of the object at `path` was placed: at `address`,
+/// inside output section number `output`.
+pub struct InputSection {
+    pub path: PathBuf,
+    pub name: String,
+    pub output: usize,
+    pub address: u32,
+    pub size: u32,
+}
+
+/// An input section dropped by garbage collection because nothing
+/// referenced it.
+pub struct DiscardedSection {
+    pub path: PathBuf,
+    pub name: String,
+    pub size: u32,
+}
+
+/// A relocated field at `location`, patched with `value`.
+pub struct PatchedRelocation {
+    pub location: Location,
+    pub symbol: String,
+    pub kind: RelocationKind,
+    pub value: u32,
+}
+
+/// A symbol only visible inside the object that defines it.
+pub struct LocalSymbol {
+    pub name: String,
+    pub location: Location,
+}
+
+/// The result of a successful link, held in memory. Sections, input
+/// sections and relocations are in ascending address order. `definitions`
+/// holds the symbols defined by an input; the rest of `symbols` were
+/// defined by the linker or a script. `local_symbols` are grouped by object
+/// in input order. `absolute_symbols` names the symbols whose value is not
+/// an address in any section. `code_fill` and `data_fill` pad a flat
+/// binary after code and other sections.
+pub struct Image {
+    pub format: OutputFormat,
+    pub base_address: u32,
+    pub entry: u32,
+    pub sections: Vec<OutputSection>,
+    pub symbols: HashMap<String, u32>,
+    pub inputs: Vec<InputSection>,
+    pub definitions: HashMap<String, Location>,
+    pub local_symbols: Vec<LocalSymbol>,
+    pub absolute_symbols: HashSet<String>,
+    pub relocations: Vec<PatchedRelocation>,
+    pub discarded: Vec<DiscardedSection>,
+    pub code_fill: u8,
+    pub data_fill: u8,
+}
+
+impl Image {
+    /// The number of bytes garbage collection removed from the output.
+    pub fn discarded_bytes(&self) -> u64 {
+        self.discarded
+            .iter()
+            .map(|section| section.size as u64)
+            .sum()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self.format {
+            OutputFormat::Flat => self.flat_bytes(),
+            OutputFormat::Elf32 => write_elf32(self),
+        }
+    }
+
+    /// Every section at its offset from the base address, with the gap
+    /// after each filled with its fill byte. `NoBits` sections are zeros if
+    /// anything follows them, and left out at the end of the image.
+    fn flat_bytes(&self) -> Vec<u8> {
+        let Some(file_end) = self
+            .sections
+            .iter()
+            .filter(|section| section.kind == SectionKind::ProgBits)
+            .map(OutputSection::end)
+            .max()
+        else {
+            return Vec::new();
+        };
+        let mut out = Vec::new();
+        let mut fill = 0;
+        for section in self
+            .sections
+            .iter()
+            .filter(|section| section.address >= self.base_address && section.address < file_end)
+        {
+            out.resize((section.address - self.base_address) as usize, fill);
+            match section.kind {
+                SectionKind::ProgBits => out.extend_from_slice(&section.data),
+                SectionKind::NoBits => out.resize(out.len() + section.size as usize, 0),
+            }
+            fill = if section.is_code() {
+                self.code_fill
+            } else {
+                self.data_fill
+            };
+        }
+        out
+    }
+
+    /// Writes a text map of where every input section, symbol and relocated
+    /// field ended up.
+    pub fn write_map_to(&self, path: impl AsRef<Path>) -> Result<(), LinkError> {
+        let path = path.as_ref();
+        std::fs::write(path, write_map(self)).map_err(|source| LinkError::Io {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), LinkError> {
+        let path = path.as_ref();
+        File::create(path)
+            .and_then(|mut output| {
+                output.write_all(&self.to_bytes())?;
+                #[cfg(unix)]
+                if self.format == OutputFormat::Elf32 {
+                    use std::os::unix::fs::PermissionsExt;
+                    output.set_permissions(std::fs::Permissions::from_mode(0o755))?;
+                }
+                Ok(())
+            })
+            .map_err(|source| LinkError::Io {
+                path: path.to_owned(),
+                source,
+            })
+    }
+}
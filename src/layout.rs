@@ -0,0 +1,795 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::LinkError;
+use crate::image::OutputSection;
+use crate::linker::ENTRY_STUB_SIZE;
+use crate::object::{Binding, ObjectFile, SectionKind};
+use crate::script::{Assignment, Command, Env, Expr, MemoryRegion, Script, SectionCommand};
+
+/// `writable_align` is the alignment of a writable output section placed
+/// right after a read-only one, so the two can be mapped with different
+/// permissions.
+pub(crate) struct LayoutOptions {
+    pub(crate) image_base: u32,
+    pub(crate) code_fill: u8,
+    pub(crate) data_fill: u8,
+    pub(crate) entry_stub: bool,
+    pub(crate) writable_align: u32,
+}
+
+/// Where an input section ended up: `offset` bytes into output section
+/// number `output`.
+pub(crate) struct Placement {
+    pub(crate) output: usize,
+    pub(crate) offset: u32,
+}
+
+impl Placement {
+    pub(crate) fn address(&self, sections: &[OutputSection]) -> u32 {
+        sections[self.output].address + self.offset
+    }
+}
+
+/// The output sections in ascending address order, where each input
+/// section was placed, indexed by object and then by section, the symbols
+/// the script assigned, and the output section that starts with room for
+/// the entry stub.
+pub(crate) struct Layout {
+    pub(crate) sections: Vec<OutputSection>,
+    pub(crate) placements: Vec<Vec<Placement>>,
+    pub(crate) symbols: HashMap<String, u32>,
+    pub(crate) stub: Option<usize>,
+}
+
+enum Item<'a> {
+    Assign(&'a Assignment),
+    Inputs(Vec<(usize, usize)>),
+}
+
+/// Places output sections one after another, tracking the location
+/// counter `.` that script expressions see.
+struct Builder<'a> {
+    objects: &'a [ObjectFile],
+    regions: &'a [MemoryRegion],
+    options: &'a LayoutOptions,
+    sections: Vec<OutputSection>,
+    input_counts: Vec<usize>,
+    placements: Vec<Vec<Option<Placement>>>,
+    symbols: HashMap<String, u32>,
+    cursors: Vec<u32>,
+    dot: u32,
+    stub: Option<usize>,
+}
+
+/// Places the input sections of `objects` as `script` directs, with `.`
+/// starting at the image base. Each input section goes to the first rule
+/// whose pattern matches it. Sections no rule claims join the rule named
+/// after their `output_section_name`, or their own name, if there is one.
+/// The rest are grouped by `output_section_name` and placed after
+/// everything else, ordered code, read-only data, data, any other sections,
+/// then the sections that take no file space; without a script that is the
+/// whole layout. Each input section is padded to its alignment with the
+/// fill byte, and an output section is aligned to the largest alignment of
+/// its inputs.
+pub(crate) fn layout(
+    script: &Script,
+    objects: &[ObjectFile],
+    options: &LayoutOptions,
+) -> Result<Layout, LinkError> {
+    let mut claimed: Vec<Vec<bool>> = objects
+        .iter()
+        .map(|obj_file| vec![false; obj_file.sections.len()])
+        .collect();
+    let mut rule_items = Vec::new();
+    for command in &script.commands {
+        let Command::Section(rule) = command else {
+            continue;
+        };
+        let items: Vec<Item> = rule
+            .contents
+            .iter()
+            .map(|content| match content {
+                SectionCommand::Assign(assignment) => Item::Assign(assignment),
+                SectionCommand::Input(pattern) => {
+                    let mut inputs = Vec::new();
+                    for (i, obj_file) in objects.iter().enumerate() {
+                        for (j, section) in obj_file.sections.iter().enumerate() {
+                            if !claimed[i][j] && pattern.matches(&obj_file.path, &section.name) {
+                                claimed[i][j] = true;
+                                inputs.push((i, j));
+                            }
+                        }
+                    }
+                    Item::Inputs(inputs)
+                }
+            })
+            .collect();
+        rule_items.push(items);
+    }
+
+    // Unclaimed sections whose output section a rule already names are
+    // appended to that rule's output section, as in GNU ld.
+    let rule_names: Vec<&str> = script
+        .commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::Section(rule) => Some(rule.name.as_str()),
+            Command::Assign(_) => None,
+        })
+        .collect();
+    let mut joined = vec![Vec::new(); rule_names.len()];
+    for (i, obj_file) in objects.iter().enumerate() {
+        for (j, section) in obj_file.sections.iter().enumerate() {
+            if claimed[i][j] {
+                continue;
+            }
+            let name = output_section_name(&section.name);
+            let rule = rule_names
+                .iter()
+                .position(|&rule| rule == name)
+                .or_else(|| rule_names.iter().position(|&rule| rule == section.name));
+            if let Some(rule) = rule {
+                claimed[i][j] = true;
+                joined[rule].push((i, j));
+            }
+        }
+    }
+    for (items, inputs) in rule_items.iter_mut().zip(joined) {
+        if !inputs.is_empty() {
+            items.push(Item::Inputs(inputs));
+        }
+    }
+
+    let mut builder = Builder {
+        objects,
+        regions: &script.regions,
+        options,
+        sections: Vec::new(),
+        input_counts: Vec::new(),
+        placements: objects
+            .iter()
+            .map(|obj_file| obj_file.sections.iter().map(|_| None).collect())
+            .collect(),
+        symbols: HashMap::new(),
+        cursors: script.regions.iter().map(|region| region.origin).collect(),
+        dot: options.image_base,
+        stub: None,
+    };
+
+    let mut rule_items = rule_items.into_iter();
+    for command in &script.commands {
+        match command {
+            Command::Assign(assignment) => builder.assign(assignment, None)?,
+            Command::Section(rule) => {
+                let items = rule_items.next().unwrap();
+                let address = match &rule.address {
+                    Some(address) => Some(address.eval(&builder)?),
+                    None => None,
+                };
+                let align = match &rule.align {
+                    Some(align) => builder.alignment(align)?,
+                    None => 1,
+                };
+                let region = rule.region.as_ref().map(|name| {
+                    script
+                        .regions
+                        .iter()
+                        .position(|region| &region.name == name)
+                        .unwrap()
+                });
+                builder.place(&rule.name, address, align, region, items)?;
+            }
+        }
+    }
+
+    let mut orphans: Vec<(&str, Vec<(usize, usize)>)> = Vec::new();
+    if options.entry_stub && builder.stub.is_none() {
+        orphans.push((".text", Vec::new()));
+    }
+    for (i, obj_file) in objects.iter().enumerate() {
+        for (j, section) in obj_file.sections.iter().enumerate() {
+            if claimed[i][j] {
+                continue;
+            }
+            let name = output_section_name(&section.name);
+            match orphans.iter_mut().find(|(orphan, _)| *orphan == name) {
+                Some((_, inputs)) => inputs.push((i, j)),
+                None => orphans.push((name, vec![(i, j)])),
+            }
+        }
+    }
+    orphans.sort_by_key(|(name, inputs)| {
+        (
+            !inputs.is_empty()
+                && inputs
+                    .iter()
+                    .all(|&(i, j)| objects[i].sections[j].kind == SectionKind::NoBits),
+            section_rank(name),
+        )
+    });
+    for (name, inputs) in orphans {
+        builder.place(name, None, 1, None, vec![Item::Inputs(inputs)])?;
+    }
+
+    builder.finish()
+}
+
+impl Builder<'_> {
+    fn alignment(&self, expr: &Expr) -> Result<u32, LinkError> {
+        let align = expr.eval(self)?;
+        if !align.is_power_of_two() {
+            return Err(LinkError::InvalidExpression {
+                expression: expr.to_string(),
+                reason: format!("alignment {} is not a power of two", align),
+            });
+        }
+        Ok(align)
+    }
+
+    /// Evaluates an assignment. Inside output section `output`, moving `.`
+    /// forward pads the section with its fill byte.
+    fn assign(&mut self, assignment: &Assignment, output: Option<usize>) -> Result<(), LinkError> {
+        let value = assignment.expr.eval(self)?;
+        if assignment.symbol != "." {
+            self.symbols.insert(assignment.symbol.clone(), value);
+            return Ok(());
+        }
+        if let Some(output) = output {
+            if value < self.dot {
+                return Err(LinkError::InvalidExpression {
+                    expression: assignment.expr.to_string(),
+                    reason: "'.' cannot move backwards inside a section".to_owned(),
+                });
+            }
+            self.dot = value;
+            self.pad(output);
+        }
+        self.dot = value;
+        Ok(())
+    }
+
+    /// Extends output section `output` up to `.`.
+    fn pad(&mut self, output: usize) {
+        let section = &mut self.sections[output];
+        section.size = self.dot - section.address;
+        if section.kind == SectionKind::ProgBits {
+            let fill = if section.is_code() {
+                self.options.code_fill
+            } else {
+                self.options.data_fill
+            };
+            section.data.resize(section.size as usize, fill);
+        }
+    }
+
+    fn place(
+        &mut self,
+        name: &str,
+        address: Option<u32>,
+        align: u32,
+        region: Option<usize>,
+        items: Vec<Item>,
+    ) -> Result<(), LinkError> {
+        let objects = self.objects;
+        let inputs = || {
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    Item::Inputs(inputs) => Some(inputs),
+                    Item::Assign(_) => None,
+                })
+                .flatten()
+                .map(|&(i, j)| &objects[i].sections[j])
+        };
+        // The stub goes at the start of the first output section with code.
+        let stub = self.options.entry_stub
+            && self.stub.is_none()
+            && (name == ".text"
+                || inputs().any(|section| output_section_name(&section.name) == ".text"));
+        let kind = if stub || inputs().any(|section| section.kind == SectionKind::ProgBits) {
+            SectionKind::ProgBits
+        } else {
+            SectionKind::NoBits
+        };
+        let align = inputs().fold(align, |align, section| align.max(section.align));
+        let overflow = || LinkError::AddressOverflow {
+            section: name.to_owned(),
+        };
+        let mut section = OutputSection {
+            name: name.to_owned(),
+            kind,
+            align,
+            address: 0,
+            data: Vec::new(),
+            size: 0,
+        };
+        let after_read_only = self
+            .sections
+            .iter()
+            .rev()
+            .find(|section| section.size > 0)
+            .is_some_and(|previous| !previous.is_writable());
+        let align = if address.is_none() && section.is_writable() && after_read_only {
+            align.max(self.options.writable_align)
+        } else {
+            align
+        };
+        let start = address
+            .unwrap_or(match region {
+                Some(region) => self.cursors[region],
+                None => self.dot,
+            })
+            .checked_next_multiple_of(align)
+            .ok_or_else(overflow)?;
+        section.address = start;
+
+        let output = self.sections.len();
+        self.sections.push(section);
+        self.input_counts.push(inputs().count());
+        self.dot = start;
+        if stub {
+            self.stub = Some(output);
+            self.dot = self
+                .dot
+                .checked_add(ENTRY_STUB_SIZE as u32)
+                .ok_or_else(overflow)?;
+            self.pad(output);
+        }
+
+        for item in items {
+            match item {
+                Item::Assign(assignment) => self.assign(assignment, Some(output))?,
+                Item::Inputs(inputs) => {
+                    for (i, j) in inputs {
+                        let section = &objects[i].sections[j];
+                        self.dot = self
+                            .dot
+                            .checked_next_multiple_of(section.align)
+                            .ok_or_else(overflow)?;
+                        self.pad(output);
+                        let output_section = &mut self.sections[output];
+                        if output_section.kind == SectionKind::ProgBits {
+                            match section.kind {
+                                SectionKind::ProgBits => {
+                                    output_section.data.extend_from_slice(&section.data)
+                                }
+                                SectionKind::NoBits => output_section
+                                    .data
+                                    .resize((output_section.size + section.size) as usize, 0),
+                            }
+                        }
+                        self.placements[i][j] = Some(Placement {
+                            output,
+                            offset: self.dot - start,
+                        });
+                        self.dot = self.dot.checked_add(section.size).ok_or_else(overflow)?;
+                        self.pad(output);
+                    }
+                }
+            }
+        }
+        self.pad(output);
+
+        if let Some(region) = region {
+            let memory = &self.regions[region];
+            let end = self.dot as u64;
+            let limit = memory.origin as u64 + memory.length as u64;
+            if end > limit {
+                return Err(LinkError::RegionOverflow {
+                    region: memory.name.clone(),
+                    section: name.to_owned(),
+                    overflow: end - limit,
+                });
+            }
+            self.cursors[region] = self.dot;
+        }
+        Ok(())
+    }
+
+    /// Drops output sections that received nothing and sorts the rest by
+    /// address, failing if any two overlap or if the entry stub would not be
+    /// the first byte of the image.
+    fn finish(self) -> Result<Layout, LinkError> {
+        let mut order: Vec<usize> = (0..self.sections.len())
+            .filter(|&i| self.input_counts[i] > 0 || self.sections[i].size > 0)
+            .collect();
+        order.sort_by_key(|&i| (self.sections[i].address, self.sections[i].size));
+        let mut new_index = vec![usize::MAX; self.sections.len()];
+        for (new, &old) in order.iter().enumerate() {
+            new_index[old] = new;
+        }
+
+        let mut old_sections: Vec<Option<OutputSection>> =
+            self.sections.into_iter().map(Some).collect();
+        let sections: Vec<OutputSection> = order
+            .iter()
+            .map(|&i| old_sections[i].take().unwrap())
+            .collect();
+        // Every section, empty ones included, must start at or after the
+        // end of all those below it.
+        let mut highest: Option<&OutputSection> = None;
+        for section in &sections {
+            if let Some(highest) = highest.filter(|highest| highest.end() > section.address) {
+                return Err(LinkError::SectionOverlap {
+                    first: highest.name.clone(),
+                    second: section.name.clone(),
+                });
+            }
+            if highest.is_none_or(|highest| section.end() > highest.end()) {
+                highest = Some(section);
+            }
+        }
+
+        let stub = self.stub.map(|stub| new_index[stub]);
+        if let Some(stub) = stub {
+            let first = sections
+                .iter()
+                .position(|section| section.kind == SectionKind::ProgBits && section.size > 0)
+                .unwrap();
+            if first != stub {
+                return Err(LinkError::EntryStubNotFirst {
+                    section: sections[stub].name.clone(),
+                    first: sections[first].name.clone(),
+                });
+            }
+        }
+
+        let placements = self
+            .placements
+            .into_iter()
+            .map(|placements| {
+                placements
+                    .into_iter()
+                    .map(|placement| {
+                        let placement = placement.unwrap();
+                        Placement {
+                            output: new_index[placement.output],
+                            offset: placement.offset,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Layout {
+            sections,
+            placements,
+            symbols: self.symbols,
+            stub,
+        })
+    }
+}
+
+impl Env for Builder<'_> {
+    fn dot(&self) -> Option<u32> {
+        Some(self.dot)
+    }
+
+    /// Symbols assigned by the script so far, then those defined in input
+    /// sections that have been placed, global definitions before weak ones.
+    fn symbol(&self, name: &str) -> Option<u32> {
+        if let Some(&value) = self.symbols.get(name) {
+            return Some(value);
+        }
+        let sections = &self.sections;
+        self.objects
+            .iter()
+            .zip(&self.placements)
+            .flat_map(|(obj_file, placements)| {
+                obj_file
+                    .symbols
+                    .iter()
+                    .filter(move |symbol| symbol.name == name)
+                    .filter_map(move |symbol| {
+                        if symbol.is_absolute() {
+                            return Some((symbol.binding, symbol.offset));
+                        }
+                        let placement = placements[symbol.section as usize].as_ref()?;
+                        Some((symbol.binding, placement.address(sections) + symbol.offset))
+                    })
+            })
+            .min_by_key(|(binding, _)| *binding == Binding::Weak)
+            .map(|(_, address)| address)
+    }
+
+    fn region(&self, name: &str) -> Option<&MemoryRegion> {
+        self.regions.iter().find(|region| region.name == name)
+    }
+
+    fn section(&self, name: &str) -> Option<(u32, u32)> {
+        self.sections
+            .iter()
+            .rev()
+            .find(|section| section.name == name)
+            .map(|section| (section.address, section.size))
+    }
+}
+
+/// The name of the output section `layout` puts section `section` of the
+/// object at `path` in.
+pub(crate) fn output_section_of<'a>(script: &'a Script, path: &Path, section: &'a str) -> &'a str {
+    let rules = script.commands.iter().filter_map(|command| match command {
+        Command::Section(rule) => Some(rule),
+        Command::Assign(_) => None,
+    });
+    let claimed = rules.clone().find(|rule| {
+        rule.contents.iter().any(|content| {
+            matches!(content, SectionCommand::Input(pattern) if pattern.matches(path, section))
+        })
+    });
+    if let Some(rule) = claimed {
+        return &rule.name;
+    }
+    let name = output_section_name(section);
+    rules
+        .clone()
+        .find(|rule| rule.name == name)
+        .or_else(|| rules.clone().find(|rule| rule.name == section))
+        .map_or(name, |rule| &rule.name)
+}
+
+/// Input sections named `.text.foo` and the like are merged into the output
+/// section of their standard prefix, and common symbols go to `.bss`; any
+/// other name gets its own.
+fn output_section_name(name: &str) -> &str {
+    if name == "COMMON" {
+        return ".bss";
+    }
+    for standard in [".text", ".rodata", ".data", ".bss"] {
+        if name == standard
+            || name
+                .strip_prefix(standard)
+                .is_some_and(|rest| rest.starts_with('.'))
+        {
+            return standard;
+        }
+    }
+    name
+}
+
+fn section_rank(name: &str) -> u8 {
+    match name {
+        ".text" => 0,
+        ".rodata" => 1,
+        ".data" => 2,
+        ".bss" => 4,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::tests::{object, Contents};
+
+    const OPTIONS: LayoutOptions = LayoutOptions {
+        image_base: 0,
+        code_fill: 0x90,
+        data_fill: 0,
+        entry_stub: false,
+        writable_align: 1,
+    };
+
+    fn script(source: &str) -> Script {
+        Script::parse(Path::new("test.ld"), source).unwrap()
+    }
+
+    fn sections(layout: &Layout) -> Vec<(&str, u32, u32)> {
+        layout
+            .sections
+            .iter()
+            .map(|section| (section.name.as_str(), section.address, section.size))
+            .collect()
+    }
+
+    #[test]
+    fn default_layout_groups_and_orders_sections() {
+        let objects = [
+            object(
+                "a.o",
+                &[
+                    (".bss", Contents::Zeroed(8)),
+                    (".data", Contents::Data(b"dd")),
+                    (".text", Contents::Data(b"tttt")),
+                ],
+                &[],
+                &[],
+            ),
+            object("b.o", &[(".text.hot", Contents::Data(b"hh"))], &[], &[]),
+        ];
+        let layout = layout(&Script::default(), &objects, &OPTIONS).unwrap();
+        assert_eq!(
+            sections(&layout),
+            [(".text", 0, 6), (".data", 6, 2), (".bss", 8, 8)]
+        );
+        assert_eq!(layout.sections[0].data, b"tttthh");
+        assert_eq!(layout.placements[1][0].address(&layout.sections), 4);
+    }
+
+    #[test]
+    fn reports_region_overflow() {
+        let objects = [object(
+            "a.o",
+            &[(".text", Contents::Data(&[0; 24]))],
+            &[],
+            &[],
+        )];
+        let script = script(
+            "MEMORY { rom : ORIGIN = 0x100, LENGTH = 16 }
+            SECTIONS { .text : { *(.text) } > rom }",
+        );
+        let error = layout(&script, &objects, &OPTIONS).err().unwrap();
+        let LinkError::RegionOverflow {
+            region,
+            section,
+            overflow,
+        } = error
+        else {
+            panic!("unexpected error: {}", error);
+        };
+        assert_eq!(
+            (region.as_str(), section.as_str(), overflow),
+            ("rom", ".text", 8)
+        );
+    }
+
+    #[test]
+    fn reports_section_overlap() {
+        let objects = [object(
+            "a.o",
+            &[
+                (".text", Contents::Data(&[0; 8])),
+                (".data", Contents::Data(&[0; 8])),
+            ],
+            &[],
+            &[],
+        )];
+        let script = script(
+            "SECTIONS {
+                .text 0x100 : { *(.text) }
+                .data 0x104 : { *(.data) }
+            }",
+        );
+        let error = layout(&script, &objects, &OPTIONS).err().unwrap();
+        let LinkError::SectionOverlap { first, second } = error else {
+            panic!("unexpected error: {}", error);
+        };
+        assert_eq!((first.as_str(), second.as_str()), (".text", ".data"));
+    }
+
+    #[test]
+    fn reports_empty_section_inside_another() {
+        let objects = [object(
+            "a.o",
+            &[
+                (".text", Contents::Data(&[0x90; 16])),
+                (".empty", Contents::Data(&[])),
+            ],
+            &[],
+            &[],
+        )];
+        let script = script(
+            "SECTIONS {
+                .text 0x100 : { *(.text) }
+                .empty 0x104 : { KEEP(*(.empty)) }
+            }",
+        );
+        let error = layout(&script, &objects, &OPTIONS).err().unwrap();
+        let LinkError::SectionOverlap { first, second } = error else {
+            panic!("unexpected error: {}", error);
+        };
+        assert_eq!((first.as_str(), second.as_str()), (".text", ".empty"));
+    }
+
+    #[test]
+    fn reports_overlap_with_section_below_neighbour() {
+        let objects = [object(
+            "a.o",
+            &[
+                (".text", Contents::Data(&[0; 16])),
+                (".a", Contents::Data(&[0; 2])),
+                (".b", Contents::Data(&[0; 2])),
+            ],
+            &[],
+            &[],
+        )];
+        let script = script(
+            "SECTIONS {
+                .text 0x100 : { *(.text) }
+                .a 0x102 : { *(.a) }
+                .b 0x108 : { *(.b) }
+            }",
+        );
+        let error = layout(&script, &objects, &OPTIONS).err().unwrap();
+        assert!(
+            matches!(error, LinkError::SectionOverlap { .. }),
+            "unexpected error: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn orphans_join_rule_of_their_output_section() {
+        let objects = [
+            object(
+                "a.o",
+                &[
+                    (".text", Contents::Data(b"aaaa")),
+                    (".vectors", Contents::Data(b"vvvv")),
+                ],
+                &[],
+                &[],
+            ),
+            object("b.o", &[(".text", Contents::Data(b"bbbb"))], &[], &[]),
+        ];
+        let script = script("SECTIONS { .text : { KEEP(*(.vectors)) } }");
+        let layout = layout(&script, &objects, &OPTIONS).unwrap();
+        assert_eq!(sections(&layout), [(".text", 0, 12)]);
+        assert_eq!(layout.sections[0].data, b"vvvvaaaabbbb");
+    }
+
+    #[test]
+    fn reports_address_overflow() {
+        let objects = [object(
+            "a.o",
+            &[(".text", Contents::Data(&[0; 0x2000]))],
+            &[],
+            &[],
+        )];
+        let options = LayoutOptions {
+            image_base: 0xffff_f000,
+            ..OPTIONS
+        };
+        let error = layout(&Script::default(), &objects, &options)
+            .err()
+            .unwrap();
+        let LinkError::AddressOverflow { section } = error else {
+            panic!("unexpected error: {}", error);
+        };
+        assert_eq!(section, ".text");
+    }
+
+    #[test]
+    fn entry_stub_starts_first_code_section() {
+        let objects = [object(
+            "a.o",
+            &[(".text", Contents::Data(b"aaaa"))],
+            &[],
+            &[],
+        )];
+        let options = LayoutOptions {
+            entry_stub: true,
+            ..OPTIONS
+        };
+        let script = script("SECTIONS { .code 0x100 : { *(.text) } }");
+        let layout = layout(&script, &objects, &options).unwrap();
+        assert_eq!(sections(&layout), [(".code", 0x100, 9)]);
+        assert_eq!(layout.stub, Some(0));
+        assert_eq!(layout.placements[0][0].address(&layout.sections), 0x105);
+    }
+
+    #[test]
+    fn entry_stub_must_start_image() {
+        let objects = [object(
+            "a.o",
+            &[
+                (".text", Contents::Data(b"aaaa")),
+                (".data", Contents::Data(b"dddd")),
+            ],
+            &[],
+            &[],
+        )];
+        let options = LayoutOptions {
+            entry_stub: true,
+            ..OPTIONS
+        };
+        let script = script("SECTIONS { .data 0 : { *(.data) } .text 0x100 : { *(.text) } }");
+        let error = layout(&script, &objects, &options).err().unwrap();
+        assert!(
+            matches!(error, LinkError::EntryStubNotFirst { .. }),
+            "unexpected error: {}",
+            error
+        );
+    }
+}
@@ -0,0 +1,20 @@
+mod archive;
+mod elf;
+mod error;
+mod gc;
+mod image;
+mod layout;
+mod linker;
+mod map;
+mod object;
+mod script;
+
+pub use archive::{Archive, Member};
+pub use error::{LinkError, Location, UndefinedSymbol};
+pub use image::{
+    DiscardedSection, Image, InputSection, LocalSymbol, OutputFormat, OutputSection,
+    PatchedRelocation,
+};
+pub use linker::{Linker, DEFAULT_CODE_FILL, DEFAULT_DATA_FILL, DEFAULT_ENTRY};
+pub use object::{RelocationKind, SectionKind, FORMAT_VERSION};
+pub use script::Script;
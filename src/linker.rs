@@ -0,0 +1,1393 @@
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::archive::{member_path, Archive, ARCHIVE_MAGIC};
+use crate::elf::PAGE_SIZE;
+use crate::error::{LinkError, Location, UndefinedSymbol};
+use crate::gc::collect_garbage;
+use crate::image::{
+    Image, InputSection, LocalSymbol, OutputFormat, OutputSection, PatchedRelocation,
+};
+use crate::layout::{layout, Layout, LayoutOptions, Placement};
+use crate::object::{
+    Binding, CommonSymbol, ObjectFile, RelocationKind, Section, SectionKind, Symbol,
+    ABSOLUTE_SECTION_NAME, MAGIC,
+};
+use crate::script::{Env, MemoryRegion, Script};
+
+/// The entry symbol used when `Linker::entry` is not called.
+pub const DEFAULT_ENTRY: &str = "_start";
+
+/// `jmp rel32`, followed by the displacement to the entry symbol.
+const JMP_REL32: u8 = 0xe9;
+pub(crate) const ENTRY_STUB_SIZE: usize = 5;
+
+/// Padding between input sections: `nop` in code, zero elsewhere.
+pub const DEFAULT_CODE_FILL: u8 = 0x90;
+pub const DEFAULT_DATA_FILL: u8 = 0x00;
+
+enum Input {
+    Path(PathBuf),
+    Bytes { name: PathBuf, data: Vec<u8> },
+    Library(String),
+}
+
+/// Collects input objects and output options, then links them with `link`.
+#[derive(Default)]
+pub struct Linker {
+    inputs: Vec<Input>,
+    search_paths: Vec<PathBuf>,
+    format: OutputFormat,
+    allow_multiple_definition: bool,
+    accept_v0: bool,
+    base_address: Option<u32>,
+    entry: Option<String>,
+    entry_stub: bool,
+    code_fill: Option<u8>,
+    data_fill: Option<u8>,
+    script: Option<Script>,
+    gc_sections: bool,
+    keep_symbols: Vec<String>,
+    defsyms: Vec<(String, String)>,
+    wrapped_symbols: Vec<String>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an object file or `ar` archive to be read from disk when linking.
+    /// Archive members are only linked if they define a symbol that is
+    /// undefined at that point in the input order.
+    pub fn add_path(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.inputs.push(Input::Path(path.into()));
+        self
+    }
+
+    /// Adds an object file or archive already held in memory. `name` stands in for the
+    /// path in diagnostics.
+    pub fn add_bytes(&mut self, name: impl Into<PathBuf>, data: impl Into<Vec<u8>>) -> &mut Self {
+        self.inputs.push(Input::Bytes {
+            name: name.into(),
+            data: data.into(),
+        });
+        self
+    }
+
+    /// Adds the archive `lib<name>.a`, found in the first search path that
+    /// has it when linking.
+    pub fn add_library(&mut self, name: impl Into<String>) -> &mut Self {
+        self.inputs.push(Input::Library(name.into()));
+        self
+    }
+
+    /// Appends a directory to the list searched by `add_library`.
+    pub fn search_path(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.search_paths.push(dir.into());
+        self
+    }
+
+    pub fn format(&mut self, format: OutputFormat) -> &mut Self {
+        self.format = format;
+        self
+    }
+
+    /// Keeps the first definition of a symbol defined by several inputs
+    /// instead of failing the link.
+    pub fn allow_multiple_definition(&mut self, allow: bool) -> &mut Self {
+        self.allow_multiple_definition = allow;
+        self
+    }
+
+    /// Reads inputs without the object file signature as headerless
+    /// version 0 objects instead of rejecting them.
+    pub fn accept_v0(&mut self, accept: bool) -> &mut Self {
+        self.accept_v0 = accept;
+        self
+    }
+
+    /// Sets the address the image is loaded at. Every symbol address and
+    /// absolute relocation is offset by it. Defaults to
+    /// `OutputFormat::default_base_address`.
+    pub fn base_address(&mut self, address: u32) -> &mut Self {
+        self.base_address = Some(address);
+        self
+    }
+
+    /// Names the symbol execution starts at, `DEFAULT_ENTRY` by default. The
+    /// link fails if no input defines it.
+    pub fn entry(&mut self, symbol: impl Into<String>) -> &mut Self {
+        self.entry = Some(symbol.into());
+        self
+    }
+
+    /// Places a `jmp` to the entry symbol at the start of the image, so a
+    /// flat binary can be entered at its first byte whatever the input order.
+    /// It goes at the start of the first output section with code, and the
+    /// link fails if a script places another section with contents below it.
+    pub fn entry_stub(&mut self, stub: bool) -> &mut Self {
+        self.entry_stub = stub;
+        self
+    }
+
+    /// Sets the byte used to pad between input sections in code output
+    /// sections. Defaults to `DEFAULT_CODE_FILL`.
+    pub fn code_fill(&mut self, fill: u8) -> &mut Self {
+        self.code_fill = Some(fill);
+        self
+    }
+
+    /// Sets the byte used to pad between input sections in every other output
+    /// section. Defaults to `DEFAULT_DATA_FILL`.
+    pub fn data_fill(&mut self, fill: u8) -> &mut Self {
+        self.data_fill = Some(fill);
+        self
+    }
+
+    /// Lays out the output as `script` directs instead of placing every
+    /// section one after another from the base address. The script's
+    /// `ENTRY` is used unless `entry` is also called.
+    pub fn script(&mut self, script: Script) -> &mut Self {
+        self.script = Some(script);
+        self
+    }
+
+    /// Drops input sections that nothing reachable from the entry symbol,
+    /// the symbols given to `keep_symbol` or a script `KEEP` refers to.
+    pub fn gc_sections(&mut self, gc: bool) -> &mut Self {
+        self.gc_sections = gc;
+        self
+    }
+
+    /// Treats `symbol` as referenced: archive members defining it are
+    /// linked, and garbage collection keeps its section.
+    pub fn keep_symbol(&mut self, symbol: impl Into<String>) -> &mut Self {
+        self.keep_symbols.push(symbol.into());
+        self
+    }
+
+    /// Defines `symbol` as the absolute value of `expression`, evaluated
+    /// after layout. It may use arithmetic, any symbol defined by the inputs,
+    /// the script or an earlier `defsym`, and the script functions other
+    /// than those needing `.`. It overrides any other definition.
+    pub fn defsym(
+        &mut self,
+        symbol: impl Into<String>,
+        expression: impl Into<String>,
+    ) -> &mut Self {
+        self.defsyms.push((symbol.into(), expression.into()));
+        self
+    }
+
+    /// Wraps `symbol` as GNU ld's `--wrap` does: references to `symbol` from
+    /// objects that do not define it resolve to `__wrap_<symbol>`, and
+    /// references to `__real_<symbol>` resolve to `symbol`.
+    pub fn wrap(&mut self, symbol: impl Into<String>) -> &mut Self {
+        self.wrapped_symbols.push(symbol.into());
+        self
+    }
+
+    /// Links the inputs in the order they were added.
+    pub fn link(&self) -> Result<Image, LinkError> {
+        let image_base = self
+            .base_address
+            .unwrap_or(self.format.default_base_address());
+
+        let default_script = Script::default();
+        let script = self.script.as_ref().unwrap_or(&default_script);
+        let entry_name = self
+            .entry
+            .as_deref()
+            .or(script.entry.as_deref())
+            .unwrap_or(DEFAULT_ENTRY);
+        let defsyms = self
+            .defsyms
+            .iter()
+            .map(|(name, expression)| {
+                Ok((
+                    name,
+                    Script::parse_expression(Path::new("--defsym"), expression)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, LinkError>>()?;
+        // Symbols the script or `--defsym` refer to are linked and kept like
+        // those named by `keep_symbol`.
+        let mut referenced = script.referenced_symbols();
+        for (_, expr) in &defsyms {
+            expr.symbols(&mut referenced);
+        }
+        referenced.retain(|&name| !self.defsyms.iter().any(|(defsym, _)| defsym == name));
+
+        let mut symbols = SymbolTracker::default();
+        symbols.undefined.insert(entry_name.to_owned());
+        symbols.undefined.extend(self.keep_symbols.iter().cloned());
+        symbols
+            .undefined
+            .extend(referenced.iter().map(|&name| name.to_owned()));
+        for (name, _) in &self.defsyms {
+            symbols.undefined.remove(name);
+            symbols.defined.insert(name.clone());
+        }
+
+        let mut objects = Vec::new();
+        for input in &self.inputs {
+            let library_path;
+            let (path, data) = match input {
+                Input::Path(path) => (path, Cow::Owned(read_file(path)?)),
+                Input::Bytes { name, data } => (name, Cow::Borrowed(data)),
+                Input::Library(name) => {
+                    library_path = self.find_library(name)?;
+                    (&library_path, Cow::Owned(read_file(&library_path)?))
+                }
+            };
+
+            if data.starts_with(ARCHIVE_MAGIC) {
+                let archive = Archive::parse(path, &data)?;
+                self.extract_members(path, &archive, &mut symbols, &mut objects)?;
+            } else {
+                let mut obj_file = ObjectFile::parse(path, &data, self.accept_v0)?;
+                wrap_references(&mut obj_file, &self.wrapped_symbols);
+                symbols.add(&obj_file);
+                objects.push(obj_file);
+            }
+        }
+
+        if let Some(common_object) = allocate_common_symbols(&objects)? {
+            objects.push(common_object);
+        }
+
+        let layout_options = LayoutOptions {
+            image_base,
+            code_fill: self.code_fill.unwrap_or(DEFAULT_CODE_FILL),
+            data_fill: self.data_fill.unwrap_or(DEFAULT_DATA_FILL),
+            entry_stub: self.entry_stub,
+            writable_align: match self.format {
+                OutputFormat::Flat => 1,
+                OutputFormat::Elf32 => PAGE_SIZE,
+            },
+        };
+        let mut discarded = Vec::new();
+        if self.gc_sections {
+            // A duplicate definition is an error even in a section about to
+            // be dropped; report it as a link without collection would.
+            if !self.allow_multiple_definition && has_duplicate_definition(&objects) {
+                let Layout {
+                    sections,
+                    placements,
+                    ..
+                } = layout(script, &objects, &layout_options)?;
+                self.resolve_definitions(&objects, &placements, &sections)?;
+            }
+            let mut roots = vec![entry_name];
+            roots.extend(self.keep_symbols.iter().map(String::as_str));
+            roots.extend(&referenced);
+            (objects, discarded) = collect_garbage(objects, &roots, script);
+        }
+
+        let Layout {
+            mut sections,
+            placements,
+            symbols: script_symbols,
+            stub,
+        } = layout(script, &objects, &layout_options)?;
+
+        let (mut definitions, mut absolute_symbols) =
+            self.resolve_definitions(&objects, &placements, &sections)?;
+
+        for name in script_symbols
+            .keys()
+            .chain(self.defsyms.iter().map(|(name, _)| name))
+        {
+            definitions.remove(name);
+            absolute_symbols.remove(name);
+        }
+        let mut local_symbols = Vec::new();
+        for (obj_file, placements) in objects.iter().zip(&placements) {
+            for symbol in &obj_file.local_symbols {
+                local_symbols.push(LocalSymbol {
+                    name: symbol.name.clone(),
+                    location: symbol_location(obj_file, placements, &sections, symbol)?,
+                });
+            }
+        }
+        let mut combined_symbols: HashMap<String, u32> = definitions
+            .iter()
+            .map(|(name, definition)| (name.clone(), definition.address))
+            .collect();
+        combined_symbols.extend(script_symbols);
+        // A script may place sections anywhere; a flat binary starts at the
+        // first one with contents.
+        let base_address = match &self.script {
+            Some(_) => sections
+                .iter()
+                .find(|section| section.kind == SectionKind::ProgBits)
+                .map_or(image_base, |section| section.address),
+            None => image_base,
+        };
+        for (name, address) in linker_defined_symbols(&sections, base_address) {
+            combined_symbols.entry(name).or_insert(address);
+        }
+        for (name, expr) in &defsyms {
+            let value = expr.eval(&SymbolEnv {
+                symbols: &combined_symbols,
+                script,
+                sections: &sections,
+            })?;
+            combined_symbols.insert((*name).clone(), value);
+            absolute_symbols.insert((*name).clone());
+        }
+
+        let relocations =
+            apply_relocations(&mut sections, &combined_symbols, &objects, &placements)?;
+
+        let entry = *combined_symbols
+            .get(entry_name)
+            .ok_or_else(|| LinkError::UndefinedEntry {
+                name: entry_name.to_owned(),
+            })?;
+
+        if let Some(stub) = stub {
+            let text = &mut sections[stub];
+            let displacement = entry.wrapping_sub(text.address + ENTRY_STUB_SIZE as u32);
+            text.data[0] = JMP_REL32;
+            text.data[1..ENTRY_STUB_SIZE].copy_from_slice(&displacement.to_le_bytes());
+        }
+
+        let mut inputs = Vec::new();
+        for (obj_file, placements) in objects.iter().zip(&placements) {
+            for (section, placement) in obj_file.sections.iter().zip(placements) {
+                inputs.push(InputSection {
+                    path: obj_file.path.clone(),
+                    name: section.name.clone(),
+                    output: placement.output,
+                    address: placement.address(&sections),
+                    size: section.size,
+                });
+            }
+        }
+        inputs.sort_by_key(|input| input.address);
+
+        Ok(Image {
+            format: self.format,
+            base_address,
+            entry,
+            sections,
+            symbols: combined_symbols,
+            inputs,
+            definitions,
+            local_symbols,
+            absolute_symbols,
+            relocations,
+            discarded,
+            code_fill: layout_options.code_fill,
+            data_fill: layout_options.data_fill,
+        })
+    }
+
+    /// Maps each global symbol to where it is defined. A strong definition
+    /// replaces a weak one, a later weak one is ignored, and two strong ones
+    /// are an error unless multiple definitions are allowed. Also returns
+    /// the names whose winning definition is absolute.
+    fn resolve_definitions(
+        &self,
+        objects: &[ObjectFile],
+        placements: &[Vec<Placement>],
+        sections: &[OutputSection],
+    ) -> Result<(HashMap<String, Location>, HashSet<String>), LinkError> {
+        let mut definitions = HashMap::<String, Location>::new();
+        let mut weak_definitions = HashSet::new();
+        let mut absolute_symbols = HashSet::new();
+        for (obj_file, placements) in objects.iter().zip(placements) {
+            for symbol in &obj_file.symbols {
+                let location = symbol_location(obj_file, placements, sections, symbol)?;
+                match definitions.entry(symbol.name.clone()) {
+                    Entry::Occupied(mut entry) => {
+                        if symbol.binding == Binding::Weak {
+                            continue;
+                        }
+                        if weak_definitions.remove(&symbol.name) {
+                            entry.insert(location);
+                            if symbol.is_absolute() {
+                                absolute_symbols.insert(symbol.name.clone());
+                            } else {
+                                absolute_symbols.remove(&symbol.name);
+                            }
+                        } else if !self.allow_multiple_definition {
+                            return Err(LinkError::DuplicateSymbol {
+                                name: symbol.name.clone(),
+                                first: Box::new(entry.get().clone()),
+                                second: Box::new(location),
+                            });
+                        }
+                    }
+                    Entry::Vacant(entry) => {
+                        if symbol.binding == Binding::Weak {
+                            weak_definitions.insert(symbol.name.clone());
+                        }
+                        if symbol.is_absolute() {
+                            absolute_symbols.insert(symbol.name.clone());
+                        }
+                        entry.insert(location);
+                    }
+                }
+            }
+        }
+        Ok((definitions, absolute_symbols))
+    }
+
+    fn find_library(&self, name: &str) -> Result<PathBuf, LinkError> {
+        let file_name = format!("lib{}.a", name);
+        self.search_paths
+            .iter()
+            .map(|dir| dir.join(&file_name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| LinkError::LibraryNotFound {
+                name: name.to_owned(),
+                search_paths: self.search_paths.clone(),
+            })
+    }
+
+    /// Adds the members of `archive` that define a symbol that is still
+    /// undefined, repeating until no member is added, since each added
+    /// member may leave new symbols undefined.
+    fn extract_members(
+        &self,
+        path: &Path,
+        archive: &Archive,
+        symbols: &mut SymbolTracker,
+        objects: &mut Vec<ObjectFile>,
+    ) -> Result<(), LinkError> {
+        let mut member_symbols = vec![Vec::new(); archive.members.len()];
+        match &archive.symbols {
+            Some(index) => {
+                for (name, member) in index {
+                    member_symbols[*member].push(name.clone());
+                }
+                // The index leaves out version 0 objects, which have no
+                // signature, so they are read to find what they define.
+                if self.accept_v0 {
+                    for (member, names) in archive.members.iter().zip(&mut member_symbols) {
+                        if !names.is_empty() || member.data.starts_with(&MAGIC) {
+                            continue;
+                        }
+                        let member_path = member_path(path, &member.name);
+                        let obj_file = ObjectFile::parse(&member_path, &member.data, true)?;
+                        names.extend(obj_file.exported_names().cloned());
+                    }
+                }
+            }
+            None => {
+                for (member, names) in archive.members.iter().zip(&mut member_symbols) {
+                    let member_path = member_path(path, &member.name);
+                    let obj_file = ObjectFile::parse(&member_path, &member.data, self.accept_v0)?;
+                    names.extend(obj_file.exported_names().cloned());
+                }
+            }
+        }
+
+        let mut extracted = vec![false; archive.members.len()];
+        loop {
+            let mut progress = false;
+            for (i, member) in archive.members.iter().enumerate() {
+                if extracted[i]
+                    || !member_symbols[i]
+                        .iter()
+                        .any(|name| symbols.undefined.contains(name))
+                {
+                    continue;
+                }
+                let member_path = member_path(path, &member.name);
+                let mut obj_file = ObjectFile::parse(&member_path, &member.data, self.accept_v0)?;
+                wrap_references(&mut obj_file, &self.wrapped_symbols);
+                symbols.add(&obj_file);
+                objects.push(obj_file);
+                extracted[i] = true;
+                progress = true;
+            }
+            if !progress {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, LinkError> {
+    fs::read(path).map_err(|source| LinkError::Io {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// The symbols defined and referenced by the objects loaded so far, used to
+/// decide which archive members to extract. Weak references do not cause
+/// extraction.
+#[derive(Default)]
+struct SymbolTracker {
+    defined: HashSet<String>,
+    undefined: HashSet<String>,
+}
+
+impl SymbolTracker {
+    fn add(&mut self, obj_file: &ObjectFile) {
+        for name in obj_file.exported_names() {
+            self.undefined.remove(name);
+            self.defined.insert(name.clone());
+        }
+        for relocation in &obj_file.relocations {
+            if !self.defined.contains(&relocation.symbol)
+                && !obj_file.weak_references.contains(&relocation.symbol)
+                && !obj_file
+                    .local_symbols
+                    .iter()
+                    .any(|symbol| symbol.name == relocation.symbol)
+            {
+                self.undefined.insert(relocation.symbol.clone());
+            }
+        }
+    }
+}
+
+/// Whether two objects have strong definitions of the same symbol.
+fn has_duplicate_definition(objects: &[ObjectFile]) -> bool {
+    let mut defined = HashSet::new();
+    objects
+        .iter()
+        .flat_map(|obj_file| &obj_file.symbols)
+        .filter(|symbol| symbol.binding != Binding::Weak)
+        .any(|symbol| !defined.insert(&symbol.name))
+}
+
+/// Where `symbol` of `obj_file` ended up. Absolute symbols are in no
+/// section, and their value is their address.
+fn symbol_location(
+    obj_file: &ObjectFile,
+    placements: &[Placement],
+    sections: &[OutputSection],
+    symbol: &Symbol,
+) -> Result<Location, LinkError> {
+    if symbol.is_absolute() {
+        return Ok(Location {
+            path: obj_file.path.clone(),
+            section: ABSOLUTE_SECTION_NAME.to_owned(),
+            offset: 0,
+            address: symbol.offset,
+        });
+    }
+    let placement = &placements[symbol.section as usize];
+    let address = placement
+        .address(sections)
+        .checked_add(symbol.offset)
+        .ok_or_else(|| LinkError::AddressOverflow {
+            section: sections[placement.output].name.clone(),
+        })?;
+    Ok(Location {
+        path: obj_file.path.clone(),
+        section: obj_file.sections[symbol.section as usize].name.clone(),
+        offset: symbol.offset,
+        address,
+    })
+}
+
+/// What `--defsym` expressions can refer to: every symbol defined so far,
+/// the script's memory regions and the output sections. There is no `.`.
+struct SymbolEnv<'a> {
+    symbols: &'a HashMap<String, u32>,
+    script: &'a Script,
+    sections: &'a [OutputSection],
+}
+
+impl Env for SymbolEnv<'_> {
+    fn dot(&self) -> Option<u32> {
+        None
+    }
+
+    fn symbol(&self, name: &str) -> Option<u32> {
+        self.symbols.get(name).copied()
+    }
+
+    fn region(&self, name: &str) -> Option<&MemoryRegion> {
+        self.script
+            .regions
+            .iter()
+            .find(|region| region.name == name)
+    }
+
+    fn section(&self, name: &str) -> Option<(u32, u32)> {
+        self.sections
+            .iter()
+            .find(|section| section.name == name)
+            .map(|section| (section.address, section.size))
+    }
+}
+
+/// Renames the references of `obj_file` for `--wrap`, before they decide
+/// which archive members to extract. An object's references to a symbol it
+/// defines itself are left alone.
+fn wrap_references(obj_file: &mut ObjectFile, wrapped_symbols: &[String]) {
+    if wrapped_symbols.is_empty() {
+        return;
+    }
+    let defines = |name: &str| {
+        obj_file
+            .exported_names()
+            .chain(obj_file.local_symbols.iter().map(|symbol| &symbol.name))
+            .any(|defined| defined == name)
+    };
+    let mut renames = HashMap::new();
+    for symbol in wrapped_symbols {
+        if !defines(symbol) {
+            renames.insert(symbol.clone(), format!("__wrap_{}", symbol));
+        }
+        renames.insert(format!("__real_{}", symbol), symbol.clone());
+    }
+    for name in obj_file
+        .relocations
+        .iter_mut()
+        .map(|relocation| &mut relocation.symbol)
+        .chain(&mut obj_file.weak_references)
+    {
+        if let Some(renamed) = renames.get(name) {
+            name.clone_from(renamed);
+        }
+    }
+}
+
+/// Merges the common symbols of every object, keeping the largest size and
+/// alignment of each, and allocates those no object defines as global as
+/// `COMMON` sections of an object of their own, which the layout places in
+/// `.bss`. A definition smaller than the common symbol fails the link.
+fn allocate_common_symbols(objects: &[ObjectFile]) -> Result<Option<ObjectFile>, LinkError> {
+    let mut commons = Vec::<CommonSymbol>::new();
+    let mut index = HashMap::<&str, usize>::new();
+    for common in objects.iter().flat_map(|obj_file| &obj_file.common_symbols) {
+        match index.entry(common.name.as_str()) {
+            Entry::Occupied(entry) => {
+                let merged = &mut commons[*entry.get()];
+                merged.size = merged.size.max(common.size);
+                merged.align = merged.align.max(common.align);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(commons.len());
+                commons.push(CommonSymbol {
+                    name: common.name.clone(),
+                    size: common.size,
+                    align: common.align,
+                });
+            }
+        }
+    }
+
+    let mut common_object = ObjectFile {
+        path: PathBuf::from("(common)"),
+        sections: Vec::new(),
+        symbols: Vec::new(),
+        local_symbols: Vec::new(),
+        common_symbols: Vec::new(),
+        weak_references: Vec::new(),
+        relocations: Vec::new(),
+    };
+    for common in commons {
+        let definition = objects.iter().find_map(|obj_file| {
+            obj_file
+                .symbols
+                .iter()
+                .find(|symbol| symbol.name == common.name && symbol.binding == Binding::Global)
+                .map(|symbol| (obj_file, symbol))
+        });
+        if let Some((obj_file, symbol)) = definition {
+            if symbol.size != 0 && symbol.size < common.size {
+                return Err(LinkError::CommonSizeMismatch {
+                    name: common.name,
+                    common_size: common.size,
+                    path: obj_file.path.clone(),
+                    size: symbol.size,
+                });
+            }
+            continue;
+        }
+        common_object.symbols.push(Symbol {
+            name: common.name,
+            binding: Binding::Global,
+            section: common_object.sections.len() as u32,
+            offset: 0,
+            size: common.size,
+        });
+        common_object.sections.push(Section {
+            name: "COMMON".to_owned(),
+            kind: SectionKind::NoBits,
+            align: common.align,
+            data: Vec::new(),
+            size: common.size,
+        });
+    }
+
+    Ok((!common_object.sections.is_empty()).then_some(common_object))
+}
+
+/// Symbols the linker defines unless an input or the script already does:
+/// - `__image_start` is the start of the image, and `__image_end` and
+///   `_end` its end, including any `.bss`.
+/// - `__<name>_start` and `__<name>_end` bound each output section
+///   `.<name>`, such as `__text_start` for `.text`.
+/// - `__start_<name>` and `__stop_<name>` bound each output section whose
+///   name is a C identifier, as in GNU ld.
+/// - `__bss_start` and `__bss_end` are both the end of the image when there
+///   is no `.bss` section.
+fn linker_defined_symbols(sections: &[OutputSection], image_start: u32) -> Vec<(String, u32)> {
+    let image_end = sections
+        .iter()
+        .map(OutputSection::end)
+        .max()
+        .unwrap_or(image_start);
+    let mut symbols = vec![
+        ("__image_start".to_owned(), image_start),
+        ("__image_end".to_owned(), image_end),
+        ("_end".to_owned(), image_end),
+    ];
+    for section in sections {
+        if let Some(name) = section
+            .name
+            .strip_prefix('.')
+            .filter(|name| is_c_identifier(name))
+        {
+            symbols.push((format!("__{}_start", name), section.address));
+            symbols.push((format!("__{}_end", name), section.end()));
+        }
+        if is_c_identifier(&section.name) {
+            symbols.push((format!("__start_{}", section.name), section.address));
+            symbols.push((format!("__stop_{}", section.name), section.end()));
+        }
+    }
+    symbols.push(("__bss_start".to_owned(), image_end));
+    symbols.push(("__bss_end".to_owned(), image_end));
+    symbols
+}
+
+fn is_c_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Patches every relocated field. A relocation refers to a local symbol of
+/// its own object if there is one. A reference to a symbol nothing defines
+/// resolves to 0 if the referencing object declared it weak, and fails the
+/// link otherwise.
+fn apply_relocations(
+    sections: &mut [OutputSection],
+    symbols: &HashMap<String, u32>,
+    objects: &[ObjectFile],
+    placements: &[Vec<Placement>],
+) -> Result<Vec<PatchedRelocation>, LinkError> {
+    let mut patched = Vec::new();
+    let mut unresolved_symbols = BTreeMap::<&str, Vec<Location>>::new();
+
+    for (obj_file, placements) in objects.iter().zip(placements) {
+        let mut local_symbols = HashMap::new();
+        for symbol in &obj_file.local_symbols {
+            local_symbols
+                .entry(symbol.name.as_str())
+                .or_insert(symbol_location(obj_file, placements, sections, symbol)?.address);
+        }
+        for relocation in &obj_file.relocations {
+            let placement = &placements[relocation.section as usize];
+            let address = placement
+                .address(sections)
+                .checked_add(relocation.offset)
+                .ok_or_else(|| LinkError::AddressOverflow {
+                    section: sections[placement.output].name.clone(),
+                })?;
+            let location = Location {
+                path: obj_file.path.clone(),
+                section: obj_file.sections[relocation.section as usize].name.clone(),
+                offset: relocation.offset,
+                address,
+            };
+            let symbol_address = local_symbols
+                .get(relocation.symbol.as_str())
+                .or_else(|| symbols.get(&relocation.symbol))
+                .copied()
+                .or_else(|| {
+                    obj_file
+                        .weak_references
+                        .contains(&relocation.symbol)
+                        .then_some(0)
+                });
+            if let Some(symbol_address) = symbol_address {
+                let target = symbol_address.wrapping_add_signed(relocation.addend);
+                let value = match relocation.kind {
+                    RelocationKind::Absolute32 => target,
+                    RelocationKind::Relative32 => {
+                        let next =
+                            address
+                                .checked_add(4)
+                                .ok_or_else(|| LinkError::AddressOverflow {
+                                    section: sections[placement.output].name.clone(),
+                                })?;
+                        target.wrapping_sub(next)
+                    }
+                };
+                let index = (placement.offset + relocation.offset) as usize;
+                sections[placement.output].data[index..index + 4]
+                    .copy_from_slice(&value.to_le_bytes());
+                patched.push(PatchedRelocation {
+                    location,
+                    symbol: relocation.symbol.clone(),
+                    kind: relocation.kind,
+                    value,
+                });
+            } else {
+                unresolved_symbols
+                    .entry(&relocation.symbol)
+                    .or_default()
+                    .push(location);
+            }
+        }
+    }
+
+    if !unresolved_symbols.is_empty() {
+        return Err(LinkError::UndefinedSymbols(
+            unresolved_symbols
+                .into_iter()
+                .map(|(name, references)| UndefinedSymbol {
+                    name: name.to_owned(),
+                    references,
+                })
+                .collect(),
+        ));
+    }
+
+    patched.sort_by_key(|relocation| relocation.location.address);
+    Ok(patched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::Member;
+    use crate::object::tests::{
+        common_symbol, encode_object, object_bytes, relocation, symbol, weak_reference, Contents,
+    };
+    use crate::object::Binding;
+
+    fn archive(members: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut archive = Archive::new();
+        for (name, data) in members {
+            archive.members.push(Member {
+                name: (*name).to_owned(),
+                data: data.clone(),
+            });
+        }
+        archive.to_bytes().unwrap()
+    }
+
+    /// A version 0 object, which has no signature, defining `name` at the
+    /// start of a one byte code section.
+    fn v0_object(name: &str) -> Vec<u8> {
+        let mut symbol = vec![name.len() as u8];
+        symbol.extend_from_slice(name.as_bytes());
+        symbol.extend_from_slice(&0u32.to_le_bytes());
+        let end = 20 + symbol.len() as u32;
+        let mut out = Vec::new();
+        for field in [20, 1, end, 0, end] {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+        out.extend(symbol);
+        out.push(0xc3);
+        out
+    }
+
+    #[test]
+    fn extraction_reaches_fixed_point() {
+        let text = |symbols: &[(&str, u32, u32)], relocations: &[(&str, u32, u32)]| {
+            object_bytes(
+                &[(".text", Contents::Data(b"\xa1\0\0\0\0\xc3"))],
+                symbols,
+                relocations,
+            )
+        };
+        let main = text(&[("_start", 0, 0)], &[("a", 0, 1)]);
+        // `a` needs `b`, which comes earlier in the archive, so finding it
+        // takes a second pass; `c` is never needed.
+        let lib = archive(&[
+            ("b.o", text(&[("b", 0, 0)], &[])),
+            ("c.o", text(&[("c", 0, 0)], &[])),
+            ("a.o", text(&[("a", 0, 0)], &[("b", 0, 1)])),
+        ]);
+        let image = Linker::new()
+            .add_bytes("main.o", main)
+            .add_bytes("lib.a", lib)
+            .link()
+            .unwrap();
+        let inputs: Vec<_> = image
+            .inputs
+            .iter()
+            .map(|input| input.path.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(inputs, ["main.o", "lib.a(a.o)", "lib.a(b.o)"]);
+        assert_eq!(image.symbols["b"], 12);
+        assert!(!image.symbols.contains_key("c"));
+    }
+
+    #[test]
+    fn defsym_references_are_gc_roots() {
+        let main = object_bytes(
+            &[
+                (".text", Contents::Data(b"\xc3")),
+                (".data", Contents::Data(&[0; 8])),
+            ],
+            &[("_start", 0, 0), ("table", 1, 0)],
+            &[],
+        );
+        let image = Linker::new()
+            .gc_sections(true)
+            .defsym("T", "table + 4")
+            .add_bytes("main.o", main)
+            .link()
+            .unwrap();
+        assert!(image.discarded.is_empty());
+        assert_eq!(image.symbols["T"], image.symbols["table"] + 4);
+        assert!(image.absolute_symbols.contains("T"));
+    }
+
+    #[test]
+    fn defines_layout_symbols() {
+        let main = object_bytes(
+            &[
+                (".text", Contents::Data(b"\xa1\0\0\0\0\xc3")),
+                (".data", Contents::Data(b"dd")),
+                ("ctors", Contents::Data(b"cccc")),
+                (".bss", Contents::Zeroed(8)),
+            ],
+            &[("_start", 0, 0)],
+            &[("__stop_ctors", 0, 1)],
+        );
+        let image = Linker::new().add_bytes("main.o", main).link().unwrap();
+        for (name, address) in [
+            ("__image_start", 0),
+            ("__image_end", 20),
+            ("_end", 20),
+            ("__text_start", 0),
+            ("__text_end", 6),
+            ("__data_start", 6),
+            ("__data_end", 8),
+            ("__start_ctors", 8),
+            ("__stop_ctors", 12),
+            ("__bss_start", 12),
+            ("__bss_end", 20),
+        ] {
+            assert_eq!(image.symbols.get(name), Some(&address), "{}", name);
+        }
+        assert!(!image.symbols.contains_key("__start_.text"));
+        assert_eq!(image.sections[0].data[1..5], 12u32.to_le_bytes());
+    }
+
+    #[test]
+    fn inputs_override_layout_symbols() {
+        let main = object_bytes(
+            &[(".text", Contents::Data(&[0xc3; 8]))],
+            &[("_start", 0, 0), ("_end", 0, 4)],
+            &[],
+        );
+        let image = Linker::new().add_bytes("main.o", main).link().unwrap();
+        assert_eq!(image.symbols["_end"], 4);
+        assert_eq!(image.symbols["__image_end"], 8);
+    }
+
+    #[test]
+    fn start_stop_references_keep_their_sections() {
+        let main = object_bytes(
+            &[
+                (".text", Contents::Data(b"\xa1\0\0\0\0\xa1\0\0\0\0\xc3")),
+                ("ctors", Contents::Data(b"cccc")),
+                ("unused", Contents::Data(b"uuuu")),
+            ],
+            &[("_start", 0, 0)],
+            &[("__start_ctors", 0, 1), ("__stop_ctors", 0, 6)],
+        );
+        let image = Linker::new()
+            .gc_sections(true)
+            .add_bytes("main.o", main)
+            .link()
+            .unwrap();
+        let discarded: Vec<_> = image.discarded.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(discarded, ["unused"]);
+        assert_eq!(image.symbols["__start_ctors"], 11);
+        assert_eq!(image.symbols["__stop_ctors"], 15);
+    }
+
+    #[test]
+    fn duplicates_in_collected_sections_are_errors() {
+        let main = object_bytes(
+            &[(".text", Contents::Data(b"\xc3\xc3"))],
+            &[("_start", 0, 0), ("foo", 0, 1)],
+            &[],
+        );
+        let other = object_bytes(&[(".text", Contents::Data(b"\xc3"))], &[("foo", 0, 0)], &[]);
+        for gc_sections in [false, true] {
+            let error = Linker::new()
+                .gc_sections(gc_sections)
+                .add_bytes("main.o", main.clone())
+                .add_bytes("other.o", other.clone())
+                .link()
+                .err()
+                .unwrap();
+            let LinkError::DuplicateSymbol { name, second, .. } = error else {
+                panic!("unexpected error: {}", error);
+            };
+            assert_eq!(name, "foo");
+            assert_eq!(second.path, Path::new("other.o"));
+        }
+    }
+
+    #[test]
+    fn fills_gaps_between_sections() {
+        let main = object_bytes(
+            &[
+                (".text", Contents::Data(b"\xc3")),
+                (".data", Contents::Data(b"d")),
+                (".bss", Contents::Zeroed(2)),
+                (".rodata", Contents::Data(b"r")),
+            ],
+            &[("_start", 0, 0)],
+            &[],
+        );
+        let script = Script::parse(
+            Path::new("test.ld"),
+            "SECTIONS {
+                .text : { *(.text) }
+                .data 0x4 : { *(.data) }
+                .bss 0x8 : { *(.bss) }
+                .rodata 0xc : { *(.rodata) }
+            }",
+        )
+        .unwrap();
+        let image = Linker::new()
+            .script(script)
+            .code_fill(0xcc)
+            .data_fill(0xff)
+            .add_bytes("main.o", main)
+            .link()
+            .unwrap();
+        assert_eq!(
+            image.to_bytes(),
+            b"\xc3\xcc\xcc\xccd\xff\xff\xff\0\0\xff\xffr"
+        );
+    }
+
+    #[test]
+    fn extracts_unindexed_v0_archive_members() {
+        let main = object_bytes(
+            &[(".text", Contents::Data(b"\xa1\0\0\0\0\xc3"))],
+            &[("_start", 0, 0)],
+            &[("old", 0, 1)],
+        );
+        let lib = archive(&[
+            ("old.o", v0_object("old")),
+            (
+                "new.o",
+                object_bytes(&[(".text", Contents::Data(b"\xc3"))], &[("new", 0, 0)], &[]),
+            ),
+        ]);
+        let image = Linker::new()
+            .accept_v0(true)
+            .add_bytes("main.o", main)
+            .add_bytes("lib.a", lib)
+            .link()
+            .unwrap();
+        assert_eq!(image.symbols["old"], 6);
+        assert!(!image.symbols.contains_key("new"));
+    }
+
+    #[test]
+    fn resolves_relative_relocations() {
+        // call target; jmp _start; target: ret
+        let main = encode_object(
+            &[(".text", Contents::Data(b"\xe8\0\0\0\0\xe9\0\0\0\0\xc3"))],
+            &[
+                symbol("_start", Binding::Global, 0, 0),
+                symbol("target", Binding::Global, 0, 10),
+            ],
+            &[
+                relocation("target", RelocationKind::Relative32, 0, 1, 0),
+                relocation("_start", RelocationKind::Relative32, 0, 6, 0),
+            ],
+        );
+        let image = Linker::new()
+            .base_address(0x1000)
+            .add_bytes("main.o", main)
+            .link()
+            .unwrap();
+        assert_eq!(image.to_bytes(), b"\xe8\x05\0\0\0\xe9\xf6\xff\xff\xff\xc3");
+    }
+
+    #[test]
+    fn applies_negative_addends() {
+        let main = encode_object(
+            &[
+                (".text", Contents::Data(&[0; 8])),
+                (".data", Contents::Data(b"table")),
+            ],
+            &[
+                symbol("_start", Binding::Global, 0, 0),
+                symbol("end", Binding::Global, 1, 5),
+            ],
+            &[
+                relocation("end", RelocationKind::Absolute32, 0, 0, -5),
+                relocation("end", RelocationKind::Relative32, 0, 4, -7),
+            ],
+        );
+        let image = Linker::new()
+            .base_address(0x1000)
+            .add_bytes("main.o", main)
+            .link()
+            .unwrap();
+        // `end` is 0x100d; the relative field ends at 0x1008, two bytes
+        // past `end - 7`.
+        assert_eq!(image.to_bytes(), b"\x08\x10\0\0\xfe\xff\xff\xfftable");
+    }
+
+    #[test]
+    fn global_definitions_override_weak_ones() {
+        let text = |code: &'static [u8], symbols: &[Symbol]| {
+            encode_object(&[(".text", Contents::Data(code))], symbols, &[])
+        };
+        let main = text(
+            b"\xc3",
+            &[
+                symbol("_start", Binding::Global, 0, 0),
+                symbol("handler", Binding::Weak, 0, 0),
+            ],
+        );
+        let other = text(b"\x90", &[symbol("handler", Binding::Weak, 0, 0)]);
+        let strong = text(b"\xf4", &[symbol("handler", Binding::Global, 0, 0)]);
+
+        let image = Linker::new()
+            .add_bytes("main.o", main.clone())
+            .add_bytes("other.o", other.clone())
+            .add_bytes("strong.o", strong)
+            .link()
+            .unwrap();
+        assert_eq!(image.symbols["handler"], 2);
+        assert_eq!(image.definitions["handler"].path, Path::new("strong.o"));
+
+        let image = Linker::new()
+            .add_bytes("main.o", main)
+            .add_bytes("other.o", other)
+            .link()
+            .unwrap();
+        assert_eq!(image.symbols["handler"], 0);
+        assert_eq!(image.definitions["handler"].path, Path::new("main.o"));
+    }
+
+    #[test]
+    fn unresolved_weak_references_are_zero() {
+        let main = encode_object(
+            &[(".text", Contents::Data(b"\xa1\xff\xff\xff\xff\xc3"))],
+            &[
+                symbol("_start", Binding::Global, 0, 0),
+                weak_reference("hook"),
+            ],
+            &[relocation("hook", RelocationKind::Absolute32, 0, 1, 0)],
+        );
+        let image = Linker::new()
+            .base_address(0x1000)
+            .add_bytes("main.o", main)
+            .link()
+            .unwrap();
+        assert_eq!(image.to_bytes(), b"\xa1\0\0\0\0\xc3");
+        assert!(!image.symbols.contains_key("hook"));
+    }
+
+    #[test]
+    fn local_symbols_stay_in_their_object() {
+        // nop; loop: mov eax, [loop]
+        let object = |symbols: &[Symbol]| {
+            encode_object(
+                &[(".text", Contents::Data(b"\x90\xa1\0\0\0\0"))],
+                symbols,
+                &[relocation("loop", RelocationKind::Absolute32, 0, 2, 0)],
+            )
+        };
+        let main = object(&[
+            symbol("_start", Binding::Global, 0, 0),
+            symbol("loop", Binding::Local, 0, 1),
+        ]);
+        let other = object(&[symbol("loop", Binding::Local, 0, 1)]);
+        let image = Linker::new()
+            .add_bytes("main.o", main)
+            .add_bytes("other.o", other)
+            .link()
+            .unwrap();
+        assert_eq!(image.to_bytes(), b"\x90\xa1\x01\0\0\0\x90\xa1\x07\0\0\0");
+        assert!(!image.symbols.contains_key("loop"));
+    }
+
+    #[test]
+    fn merges_common_symbols() {
+        let main = encode_object(
+            &[
+                (".text", Contents::Data(b"\xc3")),
+                (".bss", Contents::Zeroed(1)),
+            ],
+            &[
+                symbol("_start", Binding::Global, 0, 0),
+                common_symbol("counter", 2, 4),
+            ],
+            &[],
+        );
+        let other = encode_object(&[], &[common_symbol("counter", 8, 2)], &[]);
+        let image = Linker::new()
+            .add_bytes("main.o", main)
+            .add_bytes("other.o", other)
+            .link()
+            .unwrap();
+        // .bss takes the 4 byte alignment of `counter` and starts at 4.
+        assert_eq!(image.symbols["counter"], 8);
+        assert_eq!(image.symbols["__bss_end"], 16);
+    }
+
+    #[test]
+    fn common_symbols_need_a_large_enough_definition() {
+        let main = encode_object(
+            &[(".text", Contents::Data(b"\xc3"))],
+            &[
+                symbol("_start", Binding::Global, 0, 0),
+                common_symbol("counter", 8, 4),
+            ],
+            &[],
+        );
+        let defined = |size| {
+            encode_object(
+                &[(".data", Contents::Data(&[0; 8]))],
+                &[Symbol {
+                    size,
+                    ..symbol("counter", Binding::Global, 0, 0)
+                }],
+                &[],
+            )
+        };
+
+        let image = Linker::new()
+            .add_bytes("main.o", main.clone())
+            .add_bytes("counter.o", defined(8))
+            .link()
+            .unwrap();
+        assert_eq!(image.definitions["counter"].path, Path::new("counter.o"));
+
+        let error = Linker::new()
+            .add_bytes("main.o", main)
+            .add_bytes("counter.o", defined(4))
+            .link()
+            .err()
+            .unwrap();
+        let LinkError::CommonSizeMismatch {
+            name,
+            common_size,
+            path,
+            size,
+        } = error
+        else {
+            panic!("unexpected error: {}", error);
+        };
+        assert_eq!(
+            (name.as_str(), common_size, path.as_path(), size),
+            ("counter", 8, Path::new("counter.o"), 4)
+        );
+    }
+
+    #[test]
+    fn wraps_references() {
+        let text = |symbols: &[(&str, u32, u32)], relocations: &[(&str, u32, u32)]| {
+            object_bytes(
+                &[(".text", Contents::Data(b"\xa1\0\0\0\0\xc3"))],
+                symbols,
+                relocations,
+            )
+        };
+        let main = text(&[("_start", 0, 0)], &[("malloc", 0, 1)]);
+        // Only the renamed references of `main.o` and `wrap.o` pull in the
+        // members; `malloc.o` refers to itself.
+        let lib = archive(&[
+            ("malloc.o", text(&[("malloc", 0, 0)], &[("malloc", 0, 1)])),
+            (
+                "wrap.o",
+                text(&[("__wrap_malloc", 0, 0)], &[("__real_malloc", 0, 1)]),
+            ),
+        ]);
+        let image = Linker::new()
+            .wrap("malloc")
+            .add_bytes("main.o", main)
+            .add_bytes("libwrap.a", lib)
+            .link()
+            .unwrap();
+        let bytes = image.to_bytes();
+        let field = |symbol: &str| {
+            let address = image.symbols[symbol] as usize + 1;
+            u32::from_le_bytes(bytes[address..address + 4].try_into().unwrap())
+        };
+        assert_eq!(field("_start"), image.symbols["__wrap_malloc"]);
+        assert_eq!(field("__wrap_malloc"), image.symbols["malloc"]);
+        assert_eq!(field("malloc"), image.symbols["malloc"]);
+        assert!(!image.symbols.contains_key("__real_malloc"));
+    }
+
+    #[test]
+    fn reports_both_duplicate_definitions() {
+        let main = object_bytes(
+            &[(".text", Contents::Data(b"\x90\xc3"))],
+            &[("_start", 0, 0), ("init", 0, 1)],
+            &[],
+        );
+        let other = object_bytes(
+            &[(".text", Contents::Data(b"\x90\x90\xc3"))],
+            &[("init", 0, 2)],
+            &[],
+        );
+        let error = Linker::new()
+            .add_bytes("main.o", main)
+            .add_bytes("other.o", other)
+            .link()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "duplicate symbol 'init': defined in main.o:(.text+0x1) (0x1) \
+             and other.o:(.text+0x2) (0x4)"
+        );
+    }
+
+    #[test]
+    fn groups_undefined_references_by_symbol() {
+        let main = object_bytes(
+            &[(".text", Contents::Data(b"\xa1\0\0\0\0\xa1\0\0\0\0\xc3"))],
+            &[("_start", 0, 0)],
+            &[("zeta", 0, 1), ("alpha", 0, 6)],
+        );
+        let other = object_bytes(
+            &[(".text", Contents::Data(b"\xa1\0\0\0\0\xc3"))],
+            &[],
+            &[("zeta", 0, 1)],
+        );
+        let error = Linker::new()
+            .add_bytes("main.o", main)
+            .add_bytes("other.o", other)
+            .link()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "undefined symbol 'alpha' (1 reference)
+  main.o:(.text+0x6) (0x6): undefined reference to 'alpha'
+undefined symbol 'zeta' (2 references)
+  main.o:(.text+0x1) (0x1): undefined reference to 'zeta'
+  other.o:(.text+0x1) (0xc): undefined reference to 'zeta'"
+        );
+    }
+}
@@ -1,197 +1,233 @@
-use std::collections::HashMap;
 use std::env;
-use std::fs::File;
-use std::io::{self, Read, Write};
+use std::fs;
+use std::path::Path;
 use std::process::exit;
 
-#[repr(C)]
-struct Header {
-    symbol_offset: u32,
-    symbol_length: u32,
-    relocation_offset: u32,
-    relocation_length: u32,
-    code_offset: u32,
+use link32::{Archive, LinkError, Linker, Member, OutputFormat, Script};
+
+const USAGE: &str = "\
+Usage: link32 [options] <object_files> -o <output_name>
+       link32 ar <operation> <archive> [files...]
+
+Options:
+  --format <format>            Output format: binary (default) or elf32
+  -e <symbol>, --entry <symbol>
+                               Start execution at <symbol> (default: _start)
+  --entry-stub                 Begin the image with a jump to the entry symbol
+  --code-fill <byte>           Pad between code sections with <byte> (default: 0x90)
+  --data-fill <byte>           Pad between other sections with <byte> (default: 0x00)
+  --base <address>, -Ttext <address>
+                               Load the image at <address> (default: 0 for binary,
+                               0x08048000 for elf32)
+  -Map <file>                  Write a map of where everything was placed to <file>
+  -T <script>, --script <script>
+                               Lay out the output as the linker script <script> directs
+  -l <name>, -l<name>          Link lib<name>.a from the library search path
+  -L <dir>, -L<dir>            Search <dir> for libraries, before the directories
+                               in LINK32_LIBRARY_PATH
+  --gc-sections                Drop sections unreachable from the entry symbol
+  -u <symbol>, --undefined <symbol>
+                               Link <symbol> from archives and keep it when
+                               collecting unused sections
+  --defsym <symbol>=<expression>
+                               Define <symbol> as the absolute value of <expression>
+  --wrap <symbol>              Resolve undefined references to <symbol> to
+                               __wrap_<symbol>, and __real_<symbol> to <symbol>
+  --allow-multiple-definition  Keep the first of several definitions of a symbol
+  --accept-v0                  Read objects without a signature as format version 0";
+
+const AR_USAGE: &str = "\
+Usage: link32 ar <operation> <archive> [files...]
+
+Operations:
+  c  Create <archive> from <files>, replacing any existing archive
+  r  Add <files> to <archive>, replacing members of the same name
+  t  List the members of <archive>
+  x  Extract <files> from <archive>, or every member if none are given
+  d  Delete the members named <files> from <archive>";
+
+const LIBRARY_PATH_VAR: &str = "LINK32_LIBRARY_PATH";
+
+enum InputArg {
+    File(String),
+    Library(String),
 }
 
-impl Header {
-    fn from_slice(slice: &[u8]) -> Self {
-        let mut iter = slice.iter();
-        Header {
-            symbol_offset: read_u32(&mut iter),
-            symbol_length: read_u32(&mut iter),
-            relocation_offset: read_u32(&mut iter),
-            relocation_length: read_u32(&mut iter),
-            code_offset: read_u32(&mut iter),
-        }
-    }
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    exit(1);
 }
 
-fn read_u32(iter: &mut std::slice::Iter<u8>) -> u32 {
-    let bytes: Vec<u8> = iter.take(4).cloned().collect();
-    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+fn fail(e: LinkError) -> ! {
+    eprintln!("Error: {}", e);
+    exit(1);
 }
 
-struct ObjectFile {
-    symbols: HashMap<String, u32>,
-    relocations: HashMap<String, Vec<u32>>,
-    code: Vec<u8>,
+fn read(path: &Path) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|source| {
+        fail(LinkError::Io {
+            path: path.to_owned(),
+            source,
+        })
+    })
 }
 
-fn read_object_file(path: &str) -> io::Result<ObjectFile> {
-    let mut file = File::open(path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
-
-    let header = Header::from_slice(&buffer[0..std::mem::size_of::<Header>()]);
-    let symbols = read_symbols(&buffer, header.symbol_offset as usize, header.symbol_length);
-    let relocations = read_relocations(&buffer, header.relocation_offset as usize, header.relocation_length);
-    let code = buffer[header.code_offset as usize..].to_vec();
-
-    Ok(ObjectFile {
-        symbols,
-        relocations,
-        code,
+fn write(path: &Path, data: &[u8]) {
+    fs::write(path, data).unwrap_or_else(|source| {
+        fail(LinkError::Io {
+            path: path.to_owned(),
+            source,
+        })
     })
 }
 
-fn read_symbols(buffer: &[u8], mut offset: usize, length: u32) -> HashMap<String, u32> {
-    let mut symbols = HashMap::new();
-
-    for _ in 0..length {
-        let name_len = buffer[offset] as usize;
-        offset += 1;
-        let name = String::from_utf8(buffer[offset..offset + name_len].to_vec()).unwrap();
-        offset += name_len;
-
-        let address = u32::from_le_bytes([
-            buffer[offset],
-            buffer[offset + 1],
-            buffer[offset + 2],
-            buffer[offset + 3],
-        ]);
-        offset += 4;
-
-        symbols.insert(name, address);
-    }
-
-    symbols
+fn member_name(path: &str) -> String {
+    Path::new(path).file_name().map_or_else(
+        || path.to_owned(),
+        |name| name.to_string_lossy().into_owned(),
+    )
 }
 
-fn read_relocations(buffer: &[u8], mut offset: usize, length: u32) -> HashMap<String, Vec<u32>> {
-    let mut relocations: HashMap<String, Vec<u32>> = HashMap::new();
-    for _ in 0..length {
-        let symbol_name_length = buffer[offset] as usize;
-        offset += 1;
-
-        let symbol_name =
-            String::from_utf8(buffer[offset..offset + symbol_name_length].to_vec()).unwrap();
-        offset += symbol_name_length;
-
-        let locations_length = u32::from_le_bytes([
-            buffer[offset],
-            buffer[offset + 1],
-            buffer[offset + 2],
-            buffer[offset + 3],
-        ]);
-        offset += 4;
-
-        for _ in 0..locations_length {
-            let relocation = u32::from_le_bytes([
-                buffer[offset],
-                buffer[offset + 1],
-                buffer[offset + 2],
-                buffer[offset + 3],
-            ]);
-            offset += 4;
-
-            if let Some(vec) = relocations.get_mut(&symbol_name) {
-                vec.push(relocation);
+fn run_ar(args: &[String]) {
+    if args.len() < 2 {
+        eprintln!("{}", AR_USAGE);
+        exit(1);
+    }
+    let operation = args[0].as_str();
+    let archive_path = Path::new(&args[1]);
+    let files = &args[2..];
+
+    let open = || Archive::parse(archive_path, &read(archive_path)).unwrap_or_else(|e| fail(e));
+    let save = |archive: &Archive| {
+        write(
+            archive_path,
+            &archive.to_bytes().unwrap_or_else(|e| fail(e)),
+        )
+    };
+
+    match operation {
+        "c" | "r" => {
+            let mut archive = if operation == "r" && archive_path.exists() {
+                open()
             } else {
-                relocations.insert(symbol_name.clone(), vec![relocation]);
+                Archive::new()
+            };
+            for file in files {
+                let member = Member {
+                    name: member_name(file),
+                    data: read(Path::new(file)),
+                };
+                match archive.members.iter_mut().find(|m| m.name == member.name) {
+                    Some(existing) => *existing = member,
+                    None => archive.members.push(member),
+                }
             }
+            save(&archive);
         }
-    }
-    relocations
-}
-
-fn link_object_files(paths: &[String], output_path: &str) -> io::Result<()> {
-    let mut combined_symbols = HashMap::new();
-    let mut combined_relocations = HashMap::<String, Vec<u32>>::new();
-    let mut combined_code = Vec::new();
-    let mut base_address = 0;
-
-    for path in paths {
-        let obj_file = read_object_file(path)?;
-
-        for (name, address) in obj_file.symbols {
-            combined_symbols.insert(name, address + base_address);
+        "t" => {
+            for member in &open().members {
+                println!("{}", member.name);
+            }
         }
-
-        for (symbol, mut addresses) in obj_file.relocations {
-            for address in addresses.iter_mut() {
-                *address += base_address;
+        "x" => {
+            let archive = open();
+            for file in files {
+                if !archive.members.iter().any(|member| &member.name == file) {
+                    eprintln!("Error: No member named '{}' in the archive.", file);
+                    exit(1);
+                }
             }
-            if let Some(existing_addresses) = combined_relocations.get_mut(&symbol) {
-                existing_addresses.extend(addresses);
-            } else {
-                combined_relocations.insert(symbol, addresses);
+            for member in &archive.members {
+                if files.is_empty() || files.contains(&member.name) {
+                    write(Path::new(&member_name(&member.name)), &member.data);
+                }
             }
         }
-
-        combined_code.extend(obj_file.code);
-        base_address = combined_code.len() as u32;
+        "d" => {
+            let mut archive = open();
+            archive
+                .members
+                .retain(|member| !files.contains(&member.name));
+            save(&archive);
+        }
+        _ => {
+            eprintln!("Error: Unknown archive operation '{}'.", operation);
+            eprintln!("{}", AR_USAGE);
+            exit(1);
+        }
     }
+}
 
-    if let Err(e) = apply_relocations(&mut combined_code, &combined_symbols, &combined_relocations)
-    {
-        eprintln!("Linking failed: {}", e);
+fn value_after(args: &[String], i: usize) -> String {
+    if i + 1 >= args.len() {
+        eprintln!("Error: No value specified after '{}'.", args[i]);
         exit(1);
     }
+    args[i + 1].clone()
+}
 
-    let mut output = File::create(output_path)?;
-    output.write_all(&combined_code)?;
-
-    Ok(())
+fn parse_number(flag: &str, value: &str) -> u32 {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.unwrap_or_else(|_| {
+        eprintln!("Error: Invalid number '{}' for '{}'.", value, flag);
+        exit(1);
+    })
 }
 
-fn apply_relocations(
-    code: &mut Vec<u8>,
-    symbols: &HashMap<String, u32>,
-    relocations: &HashMap<String, Vec<u32>>,
-) -> Result<(), String> {
-    let mut unresolved_symbols = Vec::new();
-
-    for (symbol, addresses) in relocations {
-        if let Some(&symbol_address) = symbols.get(symbol) {
-            let bytes = symbol_address.to_le_bytes();
-            for &address in addresses {
-                code[address as usize..address as usize + 4].copy_from_slice(&bytes);
-            }
-        } else {
-            unresolved_symbols.push(symbol.clone());
+fn parse_defsym(flag: &str, value: &str) -> (String, String) {
+    match value.split_once('=') {
+        Some((symbol, expression)) if !symbol.is_empty() => {
+            (symbol.to_owned(), expression.to_owned())
+        }
+        _ => {
+            eprintln!("Error: Expected '<symbol>=<expression>' for '{}'.", flag);
+            exit(1);
         }
     }
+}
 
-    if !unresolved_symbols.is_empty() {
-        return Err(format!(
-            "Unresolved symbols: {}",
-            unresolved_symbols.join(", ")
-        ));
-    }
-
-    Ok(())
+fn parse_byte(flag: &str, value: &str) -> u8 {
+    u8::try_from(parse_number(flag, value)).unwrap_or_else(|_| {
+        eprintln!("Error: '{}' does not fit in a byte for '{}'.", value, flag);
+        exit(1);
+    })
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() <= 1 {
-        eprintln!("Usage: <program> <object_files> -o <output_name>");
-        exit(1);
+        usage();
+    }
+
+    if args[1] == "ar" {
+        run_ar(&args[2..]);
+        return;
     }
 
     let mut output_name = String::new();
-    let mut object_files = Vec::new();
+    let mut inputs = Vec::new();
+    let mut search_paths = Vec::new();
+    let mut allow_multiple_definition = false;
+    let mut accept_v0 = false;
+    let mut base_address = None;
+    let mut format = OutputFormat::Flat;
+    let mut entry = None;
+    let mut entry_stub = false;
+    let mut code_fill = None;
+    let mut data_fill = None;
+    let mut script = None;
+    let mut map_file = None;
+    let mut gc_sections = false;
+    let mut keep_symbols = Vec::new();
+    let mut defsyms = Vec::new();
+    let mut wrapped_symbols = Vec::new();
 
     let mut i = 1;
     while i < args.len() {
@@ -206,8 +242,98 @@ fn main() {
             }
             output_name = args[i + 1].clone();
             i += 2;
+        } else if args[i] == "--allow-multiple-definition" {
+            allow_multiple_definition = true;
+            i += 1;
+        } else if args[i] == "--accept-v0" {
+            accept_v0 = true;
+            i += 1;
+        } else if args[i] == "--base" || args[i] == "-Ttext" {
+            base_address = Some(parse_number(&args[i], &value_after(&args, i)));
+            i += 2;
+        } else if let Some(value) = args[i]
+            .strip_prefix("--base=")
+            .or_else(|| args[i].strip_prefix("-Ttext="))
+        {
+            base_address = Some(parse_number(&args[i], value));
+            i += 1;
+        } else if args[i] == "--gc-sections" {
+            gc_sections = true;
+            i += 1;
+        } else if args[i] == "-u" || args[i] == "--undefined" {
+            keep_symbols.push(value_after(&args, i));
+            i += 2;
+        } else if let Some(symbol) = args[i]
+            .strip_prefix("--undefined=")
+            .or_else(|| args[i].strip_prefix("-u"))
+        {
+            keep_symbols.push(symbol.to_owned());
+            i += 1;
+        } else if args[i] == "--defsym" {
+            defsyms.push(parse_defsym(&args[i], &value_after(&args, i)));
+            i += 2;
+        } else if let Some(value) = args[i].strip_prefix("--defsym=") {
+            defsyms.push(parse_defsym("--defsym", value));
+            i += 1;
+        } else if args[i] == "--wrap" {
+            wrapped_symbols.push(value_after(&args, i));
+            i += 2;
+        } else if let Some(symbol) = args[i].strip_prefix("--wrap=") {
+            wrapped_symbols.push(symbol.to_owned());
+            i += 1;
+        } else if args[i] == "-Map" {
+            map_file = Some(value_after(&args, i));
+            i += 2;
+        } else if let Some(path) = args[i].strip_prefix("-Map=") {
+            map_file = Some(path.to_owned());
+            i += 1;
+        } else if args[i] == "-T" || args[i] == "--script" {
+            script = Some(value_after(&args, i));
+            i += 2;
+        } else if let Some(path) = args[i]
+            .strip_prefix("--script=")
+            .or_else(|| args[i].strip_prefix("-T"))
+        {
+            script = Some(path.to_owned());
+            i += 1;
+        } else if args[i] == "--format" {
+            format = value_after(&args, i).parse().unwrap_or_else(|e| {
+                eprintln!("Error: {}.", e);
+                exit(1);
+            });
+            i += 2;
+        } else if args[i] == "-e" || args[i] == "--entry" {
+            entry = Some(value_after(&args, i));
+            i += 2;
+        } else if let Some(value) = args[i].strip_prefix("--entry=") {
+            entry = Some(value.to_owned());
+            i += 1;
+        } else if args[i] == "--entry-stub" {
+            entry_stub = true;
+            i += 1;
+        } else if args[i] == "--code-fill" {
+            code_fill = Some(parse_byte(&args[i], &value_after(&args, i)));
+            i += 2;
+        } else if args[i] == "--data-fill" {
+            data_fill = Some(parse_byte(&args[i], &value_after(&args, i)));
+            i += 2;
+        } else if args[i] == "-l" {
+            inputs.push(InputArg::Library(value_after(&args, i)));
+            i += 2;
+        } else if let Some(name) = args[i].strip_prefix("-l") {
+            inputs.push(InputArg::Library(name.to_owned()));
+            i += 1;
+        } else if args[i] == "-L" {
+            search_paths.push(value_after(&args, i));
+            i += 2;
+        } else if let Some(dir) = args[i].strip_prefix("-L") {
+            search_paths.push(dir.to_owned());
+            i += 1;
+        } else if args[i].starts_with('-') {
+            eprintln!("Error: Unknown option '{}'.", args[i]);
+            usage();
         } else {
-            object_files.push(args[i].clone());
+            inputs.push(InputArg::File(args[i].clone()));
             i += 1;
         }
     }
@@ -217,13 +343,72 @@ fn main() {
         exit(1);
     }
 
-    if object_files.is_empty() {
+    if inputs.is_empty() {
         eprintln!("Error: No input object files specified.");
         exit(1);
     }
 
-    // Call the linker function with the collected object files and output name
-    if let Err(e) = link_object_files(&object_files, &output_name) {
+    let mut linker = Linker::new();
+    linker
+        .allow_multiple_definition(allow_multiple_definition)
+        .accept_v0(accept_v0)
+        .format(format)
+        .entry_stub(entry_stub)
+        .gc_sections(gc_sections);
+    if let Some(address) = base_address {
+        linker.base_address(address);
+    }
+    if let Some(symbol) = entry {
+        linker.entry(symbol);
+    }
+    if let Some(fill) = code_fill {
+        linker.code_fill(fill);
+    }
+    if let Some(fill) = data_fill {
+        linker.data_fill(fill);
+    }
+    if let Some(path) = script {
+        linker.script(Script::read(path).unwrap_or_else(|e| fail(e)));
+    }
+    for symbol in &keep_symbols {
+        linker.keep_symbol(symbol);
+    }
+    for (symbol, expression) in &defsyms {
+        linker.defsym(symbol, expression);
+    }
+    for symbol in &wrapped_symbols {
+        linker.wrap(symbol);
+    }
+    for dir in &search_paths {
+        linker.search_path(dir);
+    }
+    if let Some(paths) = env::var_os(LIBRARY_PATH_VAR) {
+        for dir in env::split_paths(&paths) {
+            linker.search_path(dir);
+        }
+    }
+    for input in &inputs {
+        match input {
+            InputArg::File(path) => linker.add_path(path),
+            InputArg::Library(name) => linker.add_library(name),
+        };
+    }
+
+    let result = linker.link().and_then(|image| {
+        image.write_to(&output_name)?;
+        if gc_sections {
+            eprintln!(
+                "Removed {} bytes in {} unused sections.",
+                image.discarded_bytes(),
+                image.discarded.len()
+            );
+        }
+        match &map_file {
+            Some(path) => image.write_map_to(path),
+            None => Ok(()),
+        }
+    });
+    if let Err(e) = result {
         eprintln!("Linking failed: {}", e);
         exit(1);
     }
@@ -0,0 +1,126 @@
+use std::fmt::Write;
+
+use crate::error::Location;
+use crate::image::Image;
+use crate::object::{RelocationKind, SectionKind};
+
+fn site(location: &Location) -> String {
+    format!(
+        "{}({}+{:#x})",
+        location.path.display(),
+        location.section,
+        location.offset
+    )
+}
+
+/// Formats the link map: each output section followed by the input
+/// sections placed in it, then every symbol and every patched relocation,
+/// all by ascending address, and finally any garbage collected sections.
+pub(crate) fn write_map(image: &Image) -> String {
+    let mut out = String::new();
+    writeln!(out, "Entry point: {:#010x}", image.entry).unwrap();
+
+    writeln!(out, "\nSections\n").unwrap();
+    writeln!(out, "{:<10}  {:<10}  Name", "Address", "Size").unwrap();
+    for (index, section) in image.sections.iter().enumerate() {
+        writeln!(
+            out,
+            "{:#010x}  {:#010x}  {}{}",
+            section.address,
+            section.size,
+            section.name,
+            match section.kind {
+                SectionKind::ProgBits => "",
+                SectionKind::NoBits => " (no file space)",
+            }
+        )
+        .unwrap();
+        for input in image.inputs.iter().filter(|input| input.output == index) {
+            writeln!(
+                out,
+                "  {:#010x}  {:#010x}  {}({})",
+                input.address,
+                input.size,
+                input.path.display(),
+                input.name
+            )
+            .unwrap();
+        }
+    }
+
+    // Local symbols are qualified by the file name of their object.
+    let mut symbols: Vec<(u32, String, String)> = image
+        .symbols
+        .iter()
+        .map(|(name, &address)| {
+            let defined_in = image
+                .definitions
+                .get(name)
+                .map_or_else(|| "(linker)".to_owned(), site);
+            (address, name.clone(), defined_in)
+        })
+        .chain(image.local_symbols.iter().map(|symbol| {
+            let file = symbol.location.path.file_name().unwrap_or_default();
+            (
+                symbol.location.address,
+                format!("{}:{}", file.to_string_lossy(), symbol.name),
+                site(&symbol.location),
+            )
+        }))
+        .collect();
+    symbols.sort();
+    let width = symbols
+        .iter()
+        .map(|(_, name, _)| name.len())
+        .fold("Name".len(), usize::max);
+    writeln!(out, "\nSymbols\n").unwrap();
+    writeln!(out, "{:<10}  {:<width$}  Defined in", "Address", "Name").unwrap();
+    for (address, name, defined_in) in symbols {
+        writeln!(out, "{:#010x}  {:<width$}  {}", address, name, defined_in).unwrap();
+    }
+
+    let width = image
+        .relocations
+        .iter()
+        .map(|relocation| relocation.symbol.len())
+        .fold("Symbol".len(), usize::max);
+    writeln!(out, "\nRelocations\n").unwrap();
+    writeln!(
+        out,
+        "{:<10}  Kind   {:<width$}  {:<10}  Site",
+        "Address", "Symbol", "Value"
+    )
+    .unwrap();
+    for relocation in &image.relocations {
+        writeln!(
+            out,
+            "{:#010x}  {}  {:<width$}  {:#010x}  {}",
+            relocation.location.address,
+            match relocation.kind {
+                RelocationKind::Absolute32 => "abs32",
+                RelocationKind::Relative32 => "rel32",
+            },
+            relocation.symbol,
+            relocation.value,
+            site(&relocation.location)
+        )
+        .unwrap();
+    }
+
+    if !image.discarded.is_empty() {
+        writeln!(out, "\nDiscarded sections\n").unwrap();
+        writeln!(out, "{:<10}  Name", "Size").unwrap();
+        for section in &image.discarded {
+            writeln!(
+                out,
+                "{:#010x}  {}({})",
+                section.size,
+                section.path.display(),
+                section.name
+            )
+            .unwrap();
+        }
+    }
+
+    out
+}
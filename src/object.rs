@@ -0,0 +1,890 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::LinkError;
+
+/// Every versioned object file starts with `MAGIC` followed by a `u32`
+/// format version. Version 0 objects predate the signature and start directly
+/// with the symbol table offset; they are only read when asked for.
+pub(crate) const MAGIC: [u8; 4] = *b"LK32";
+
+/// The newest object format version this linker reads. Older versions are
+/// still accepted:
+///
+/// - 1: signature and version added to the header.
+/// - 2: each relocation entry has a kind byte between its name and count.
+/// - 3: each relocated offset is followed by a signed 32-bit addend.
+/// - 4: code is split into named sections, listed in a table whose offset
+///   and count end the header; symbols and relocation entries carry the
+///   index of their section. Earlier versions hold a single `.text` section
+///   running from the code offset to the end of the file.
+/// - 5: each section table entry has a kind byte after its name. `NoBits`
+///   sections are followed only by their size and have no data in the file.
+/// - 6: each section table entry has a `u32` alignment after its kind. It
+///   must be a power of two, or 0, which like 1 means unaligned.
+/// - 7: each symbol has a binding byte after its name: 0 for global, 1 for
+///   weak, 2 for local. A weak symbol whose section index is
+///   `UNDEFINED_SECTION` is not a definition but a weak reference, which may
+///   be left unresolved.
+/// - 8: each symbol has a `u32` size after its offset, 0 if unknown. A
+///   symbol whose section index is `COMMON_SECTION` is a common symbol, a
+///   tentative definition of `size` bytes whose offset is its alignment.
+/// - 9: a symbol whose section index is `ABSOLUTE_SECTION` is absolute: its
+///   offset is its value, which does not move with the base address.
+pub const FORMAT_VERSION: u32 = 9;
+
+const RELOCATION_KIND_VERSION: u32 = 2;
+const RELOCATION_ADDEND_VERSION: u32 = 3;
+const SECTIONS_VERSION: u32 = 4;
+const SECTION_KIND_VERSION: u32 = 5;
+const SECTION_ALIGN_VERSION: u32 = 6;
+const SYMBOL_BINDING_VERSION: u32 = 7;
+const SYMBOL_SIZE_VERSION: u32 = 8;
+const ABSOLUTE_SYMBOL_VERSION: u32 = 9;
+
+/// The section index of a weak reference in the symbol table.
+const UNDEFINED_SECTION: u32 = u32::MAX;
+/// The section index of a common symbol in the symbol table.
+const COMMON_SECTION: u32 = u32::MAX - 1;
+/// The section index of an absolute symbol in the symbol table.
+const ABSOLUTE_SECTION: u32 = u32::MAX - 2;
+
+/// The section name reported for absolute symbols, as in `ld` maps.
+pub(crate) const ABSOLUTE_SECTION_NAME: &str = "*ABS*";
+
+#[repr(C)]
+struct Header {
+    version: u32,
+    symbol_offset: u32,
+    symbol_length: u32,
+    relocation_offset: u32,
+    relocation_length: u32,
+    section_offset: u32,
+    section_length: u32,
+}
+
+impl Header {
+    fn read(reader: &mut Reader, accept_v0: bool) -> Result<Self, LinkError> {
+        let version = if reader.buffer.starts_with(&MAGIC) {
+            reader.read_bytes(MAGIC.len())?;
+            let version = reader.read_u32()?;
+            if !(1..=FORMAT_VERSION).contains(&version) {
+                return Err(LinkError::UnsupportedVersion {
+                    path: reader.path.to_owned(),
+                    version,
+                });
+            }
+            version
+        } else if accept_v0 {
+            0
+        } else {
+            return Err(LinkError::BadMagic {
+                path: reader.path.to_owned(),
+            });
+        };
+
+        Ok(Header {
+            version,
+            symbol_offset: reader.read_u32()?,
+            symbol_length: reader.read_u32()?,
+            relocation_offset: reader.read_u32()?,
+            relocation_length: reader.read_u32()?,
+            section_offset: reader.read_u32()?,
+            section_length: if version >= SECTIONS_VERSION {
+                reader.read_u32()?
+            } else {
+                1
+            },
+        })
+    }
+}
+
+/// A bounds-checked cursor over an object file. Running off the end of the
+/// buffer produces the error built by `eof` for the table being read.
+struct Reader<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+    path: &'a Path,
+    eof: fn(&Reader) -> LinkError,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buffer: &'a [u8], offset: usize, path: &'a Path, eof: fn(&Reader) -> LinkError) -> Self {
+        Reader {
+            buffer,
+            offset,
+            path,
+            eof,
+        }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], LinkError> {
+        let bytes = self
+            .offset
+            .checked_add(len)
+            .and_then(|end| self.buffer.get(self.offset..end))
+            .ok_or_else(|| (self.eof)(self))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, LinkError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, LinkError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_name(&mut self) -> Result<String, LinkError> {
+        let len = self.read_u8()? as usize;
+        let offset = self.offset;
+        let bytes = self.read_bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| LinkError::InvalidUtf8 {
+            path: self.path.to_owned(),
+            offset,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelocationKind {
+    /// The symbol's address plus the addend.
+    Absolute32,
+    /// The symbol's address plus the addend, relative to the end of the
+    /// relocated field, as used by `call` and `jmp` rel32.
+    Relative32,
+}
+
+impl RelocationKind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(RelocationKind::Absolute32),
+            1 => Some(RelocationKind::Relative32),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) struct Relocation {
+    pub(crate) symbol: String,
+    pub(crate) kind: RelocationKind,
+    pub(crate) section: u32,
+    pub(crate) offset: u32,
+    pub(crate) addend: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Binding {
+    Global,
+    /// Overridden by a global definition of the same name, if any.
+    Weak,
+    /// Only visible to relocations in the same object.
+    Local,
+}
+
+impl Binding {
+    fn from_u8(binding: u8) -> Option<Self> {
+        match binding {
+            0 => Some(Binding::Global),
+            1 => Some(Binding::Weak),
+            2 => Some(Binding::Local),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) struct Symbol {
+    pub(crate) name: String,
+    pub(crate) binding: Binding,
+    pub(crate) section: u32,
+    pub(crate) offset: u32,
+    pub(crate) size: u32,
+}
+
+impl Symbol {
+    /// Whether `offset` is the symbol's value rather than an offset into
+    /// its section.
+    pub(crate) fn is_absolute(&self) -> bool {
+        self.section == ABSOLUTE_SECTION
+    }
+}
+
+/// A tentative definition, like C's `int counter;` at file scope. The
+/// linker allocates it in `.bss` unless some object defines the symbol.
+pub(crate) struct CommonSymbol {
+    pub(crate) name: String,
+    pub(crate) size: u32,
+    pub(crate) align: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SectionKind {
+    /// Contents stored in the file.
+    ProgBits,
+    /// Zero-initialized memory that takes no space in the file, like `.bss`.
+    NoBits,
+}
+
+impl SectionKind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(SectionKind::ProgBits),
+            1 => Some(SectionKind::NoBits),
+            _ => None,
+        }
+    }
+}
+
+/// A section's contents; `data` is empty for `NoBits` sections, whose
+/// length is only given by `size`. `align` is always a power of two.
+pub(crate) struct Section {
+    pub(crate) name: String,
+    pub(crate) kind: SectionKind,
+    pub(crate) align: u32,
+    pub(crate) data: Vec<u8>,
+    pub(crate) size: u32,
+}
+
+/// A parsed object. `symbols` holds the global and weak definitions, and
+/// `local_symbols` those only the object's own relocations can refer to.
+/// The names of weak references, which resolve to 0 when nothing defines
+/// them, are kept in `weak_references`.
+pub(crate) struct ObjectFile {
+    pub(crate) path: PathBuf,
+    pub(crate) sections: Vec<Section>,
+    pub(crate) symbols: Vec<Symbol>,
+    pub(crate) local_symbols: Vec<Symbol>,
+    pub(crate) common_symbols: Vec<CommonSymbol>,
+    pub(crate) weak_references: Vec<String>,
+    pub(crate) relocations: Vec<Relocation>,
+}
+
+impl ObjectFile {
+    /// The symbols this object offers to others: its global and weak
+    /// definitions and its common symbols.
+    pub(crate) fn exported_names(&self) -> impl Iterator<Item = &String> {
+        self.symbols
+            .iter()
+            .map(|symbol| &symbol.name)
+            .chain(self.common_symbols.iter().map(|symbol| &symbol.name))
+    }
+
+    pub(crate) fn parse(path: &Path, buffer: &[u8], accept_v0: bool) -> Result<Self, LinkError> {
+        let mut reader = Reader::new(buffer, 0, path, |reader| LinkError::TruncatedHeader {
+            path: reader.path.to_owned(),
+            len: reader.buffer.len(),
+        });
+        let header = Header::read(&mut reader, accept_v0)?;
+        let sections = if header.version >= SECTIONS_VERSION {
+            read_sections(
+                path,
+                buffer,
+                header.version,
+                header.section_offset as usize,
+                header.section_length,
+            )?
+        } else {
+            let code = buffer
+                .get(header.section_offset as usize..)
+                .ok_or_else(|| LinkError::SectionOutOfBounds {
+                    path: path.to_owned(),
+                    section: ".text".to_owned(),
+                    offset: header.section_offset as usize,
+                })?;
+            vec![Section {
+                name: ".text".to_owned(),
+                kind: SectionKind::ProgBits,
+                align: 1,
+                data: code.to_vec(),
+                size: code.len() as u32,
+            }]
+        };
+        let (local_symbols, symbols): (Vec<Symbol>, Vec<Symbol>) = read_symbols(
+            path,
+            buffer,
+            header.version,
+            header.symbol_offset as usize,
+            header.symbol_length,
+        )?
+        .into_iter()
+        .partition(|symbol| symbol.binding == Binding::Local);
+        let (weak_references, symbols): (Vec<Symbol>, Vec<Symbol>) =
+            symbols.into_iter().partition(|symbol| {
+                symbol.binding == Binding::Weak && symbol.section == UNDEFINED_SECTION
+            });
+        let (common_symbols, symbols): (Vec<Symbol>, Vec<Symbol>) = symbols
+            .into_iter()
+            .partition(|symbol| symbol.section == COMMON_SECTION);
+        let common_symbols = common_symbols
+            .into_iter()
+            .map(|symbol| {
+                if symbol.offset != 0 && !symbol.offset.is_power_of_two() {
+                    return Err(LinkError::InvalidCommonAlignment {
+                        path: path.to_owned(),
+                        symbol: symbol.name,
+                        align: symbol.offset,
+                    });
+                }
+                Ok(CommonSymbol {
+                    name: symbol.name,
+                    size: symbol.size,
+                    align: symbol.offset.max(1),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let relocations = read_relocations(
+            path,
+            buffer,
+            header.version,
+            header.relocation_offset as usize,
+            header.relocation_length,
+        )?;
+
+        // However they are placed, an object's sections must fit in the
+        // address space together.
+        let mut end = 0u64;
+        for section in &sections {
+            end = end.next_multiple_of(section.align as u64) + section.size as u64;
+            if end > u32::MAX as u64 {
+                return Err(LinkError::SectionTooLarge {
+                    path: path.to_owned(),
+                    section: section.name.clone(),
+                    size: section.size,
+                });
+            }
+        }
+        for symbol in symbols.iter().chain(&local_symbols) {
+            if symbol.is_absolute() && header.version >= ABSOLUTE_SYMBOL_VERSION {
+                continue;
+            }
+            let section = sections.get(symbol.section as usize).ok_or_else(|| {
+                LinkError::InvalidSectionIndex {
+                    path: path.to_owned(),
+                    symbol: symbol.name.clone(),
+                    index: symbol.section,
+                }
+            })?;
+            if symbol.offset > section.size {
+                return Err(LinkError::SymbolOutOfRange {
+                    path: path.to_owned(),
+                    section: section.name.clone(),
+                    symbol: symbol.name.clone(),
+                    offset: symbol.offset,
+                });
+            }
+        }
+        for relocation in &relocations {
+            let section = sections.get(relocation.section as usize).ok_or_else(|| {
+                LinkError::InvalidSectionIndex {
+                    path: path.to_owned(),
+                    symbol: relocation.symbol.clone(),
+                    index: relocation.section,
+                }
+            })?;
+            if relocation.offset as usize + 4 > section.data.len() {
+                return Err(LinkError::RelocationOutOfRange {
+                    path: path.to_owned(),
+                    section: section.name.clone(),
+                    symbol: relocation.symbol.clone(),
+                    offset: relocation.offset,
+                });
+            }
+        }
+
+        Ok(ObjectFile {
+            path: path.to_owned(),
+            sections,
+            symbols,
+            local_symbols,
+            common_symbols,
+            weak_references: weak_references
+                .into_iter()
+                .map(|symbol| symbol.name)
+                .collect(),
+            relocations,
+        })
+    }
+}
+
+fn read_sections(
+    path: &Path,
+    buffer: &[u8],
+    version: u32,
+    offset: usize,
+    length: u32,
+) -> Result<Vec<Section>, LinkError> {
+    let mut reader = Reader::new(buffer, offset, path, |reader| {
+        LinkError::SectionTableOutOfBounds {
+            path: reader.path.to_owned(),
+            offset: reader.offset,
+        }
+    });
+    let mut sections = Vec::new();
+
+    for _ in 0..length {
+        let name = reader.read_name()?;
+        let kind = if version >= SECTION_KIND_VERSION {
+            let kind_offset = reader.offset;
+            let kind = reader.read_u8()?;
+            SectionKind::from_u8(kind).ok_or_else(|| LinkError::UnknownSectionKind {
+                path: path.to_owned(),
+                offset: kind_offset,
+                kind,
+            })?
+        } else {
+            SectionKind::ProgBits
+        };
+        let align = if version >= SECTION_ALIGN_VERSION {
+            let align = reader.read_u32()?;
+            if align != 0 && !align.is_power_of_two() {
+                return Err(LinkError::InvalidAlignment {
+                    path: path.to_owned(),
+                    section: name,
+                    align,
+                });
+            }
+            align.max(1)
+        } else {
+            1
+        };
+
+        if kind == SectionKind::NoBits {
+            let size = reader.read_u32()?;
+            sections.push(Section {
+                name,
+                kind,
+                align,
+                data: Vec::new(),
+                size,
+            });
+            continue;
+        }
+
+        let data_offset = reader.read_u32()? as usize;
+        let size = reader.read_u32()?;
+        let data = data_offset
+            .checked_add(size as usize)
+            .and_then(|end| buffer.get(data_offset..end))
+            .ok_or_else(|| LinkError::SectionOutOfBounds {
+                path: path.to_owned(),
+                section: name.clone(),
+                offset: data_offset,
+            })?;
+
+        sections.push(Section {
+            name,
+            kind,
+            align,
+            data: data.to_vec(),
+            size,
+        });
+    }
+
+    Ok(sections)
+}
+
+fn read_symbols(
+    path: &Path,
+    buffer: &[u8],
+    version: u32,
+    offset: usize,
+    length: u32,
+) -> Result<Vec<Symbol>, LinkError> {
+    let mut reader = Reader::new(buffer, offset, path, |reader| {
+        LinkError::SymbolTableOutOfBounds {
+            path: reader.path.to_owned(),
+            offset: reader.offset,
+        }
+    });
+    let mut symbols = Vec::new();
+
+    for _ in 0..length {
+        let name = reader.read_name()?;
+        let binding = if version >= SYMBOL_BINDING_VERSION {
+            let binding_offset = reader.offset;
+            let binding = reader.read_u8()?;
+            Binding::from_u8(binding).ok_or_else(|| LinkError::UnknownBinding {
+                path: path.to_owned(),
+                offset: binding_offset,
+                binding,
+            })?
+        } else {
+            Binding::Global
+        };
+        let section = if version >= SECTIONS_VERSION {
+            reader.read_u32()?
+        } else {
+            0
+        };
+        let offset = reader.read_u32()?;
+        let size = if version >= SYMBOL_SIZE_VERSION {
+            reader.read_u32()?
+        } else {
+            0
+        };
+
+        symbols.push(Symbol {
+            name,
+            binding,
+            section,
+            offset,
+            size,
+        });
+    }
+
+    Ok(symbols)
+}
+
+fn read_relocations(
+    path: &Path,
+    buffer: &[u8],
+    version: u32,
+    offset: usize,
+    length: u32,
+) -> Result<Vec<Relocation>, LinkError> {
+    let mut reader = Reader::new(buffer, offset, path, |reader| {
+        LinkError::RelocationTableOutOfBounds {
+            path: reader.path.to_owned(),
+            offset: reader.offset,
+        }
+    });
+    let mut relocations = Vec::new();
+    for _ in 0..length {
+        let symbol_name = reader.read_name()?;
+        let kind = if version >= RELOCATION_KIND_VERSION {
+            let kind_offset = reader.offset;
+            let kind = reader.read_u8()?;
+            RelocationKind::from_u8(kind).ok_or_else(|| LinkError::UnknownRelocationKind {
+                path: path.to_owned(),
+                offset: kind_offset,
+                kind,
+            })?
+        } else {
+            RelocationKind::Absolute32
+        };
+        let section = if version >= SECTIONS_VERSION {
+            reader.read_u32()?
+        } else {
+            0
+        };
+        let locations_length = reader.read_u32()?;
+
+        for _ in 0..locations_length {
+            let offset = reader.read_u32()?;
+            let addend = if version >= RELOCATION_ADDEND_VERSION {
+                reader.read_u32()? as i32
+            } else {
+                0
+            };
+
+            relocations.push(Relocation {
+                symbol: symbol_name.clone(),
+                kind,
+                section,
+                offset,
+                addend,
+            });
+        }
+    }
+    Ok(relocations)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// The contents of a section for `object_bytes`: its data, or the size
+    /// of a `NoBits` section.
+    pub(crate) enum Contents<'a> {
+        Data(&'a [u8]),
+        Zeroed(u32),
+    }
+
+    fn push_name(out: &mut Vec<u8>, name: &str) {
+        out.push(name.len() as u8);
+        out.extend_from_slice(name.as_bytes());
+    }
+
+    /// A symbol for `encode_object`.
+    pub(crate) fn symbol(name: &str, binding: Binding, section: u32, offset: u32) -> Symbol {
+        Symbol {
+            name: name.to_owned(),
+            binding,
+            section,
+            offset,
+            size: 0,
+        }
+    }
+
+    /// A weak reference for `encode_object`.
+    pub(crate) fn weak_reference(name: &str) -> Symbol {
+        symbol(name, Binding::Weak, UNDEFINED_SECTION, 0)
+    }
+
+    /// A common symbol for `encode_object`.
+    pub(crate) fn common_symbol(name: &str, size: u32, align: u32) -> Symbol {
+        Symbol {
+            size,
+            ..symbol(name, Binding::Global, COMMON_SECTION, align)
+        }
+    }
+
+    /// A relocation for `encode_object`.
+    pub(crate) fn relocation(
+        symbol: &str,
+        kind: RelocationKind,
+        section: u32,
+        offset: u32,
+        addend: i32,
+    ) -> Relocation {
+        Relocation {
+            symbol: symbol.to_owned(),
+            kind,
+            section,
+            offset,
+            addend,
+        }
+    }
+
+    /// Encodes an object in the current format with global `symbols` given
+    /// as (name, section, offset) and `abs32` relocations given as
+    /// (symbol, section, offset).
+    pub(crate) fn object_bytes(
+        sections: &[(&str, Contents)],
+        symbols: &[(&str, u32, u32)],
+        relocations: &[(&str, u32, u32)],
+    ) -> Vec<u8> {
+        let symbols: Vec<_> = symbols
+            .iter()
+            .map(|&(name, section, offset)| symbol(name, Binding::Global, section, offset))
+            .collect();
+        let relocations: Vec<_> = relocations
+            .iter()
+            .map(|&(name, section, offset)| {
+                relocation(name, RelocationKind::Absolute32, section, offset, 0)
+            })
+            .collect();
+        encode_object(sections, &symbols, &relocations)
+    }
+
+    /// Encodes an object in the current format. Each relocation gets its
+    /// own entry.
+    pub(crate) fn encode_object(
+        sections: &[(&str, Contents)],
+        symbols: &[Symbol],
+        relocations: &[Relocation],
+    ) -> Vec<u8> {
+        let mut symbol_table = Vec::new();
+        for symbol in symbols {
+            push_name(&mut symbol_table, &symbol.name);
+            symbol_table.push(match symbol.binding {
+                Binding::Global => 0,
+                Binding::Weak => 1,
+                Binding::Local => 2,
+            });
+            for field in [symbol.section, symbol.offset, symbol.size] {
+                symbol_table.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        let mut relocation_table = Vec::new();
+        for relocation in relocations {
+            push_name(&mut relocation_table, &relocation.symbol);
+            relocation_table.push(match relocation.kind {
+                RelocationKind::Absolute32 => 0,
+                RelocationKind::Relative32 => 1,
+            });
+            for field in [
+                relocation.section,
+                1,
+                relocation.offset,
+                relocation.addend as u32,
+            ] {
+                relocation_table.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+
+        let symbol_offset = 32;
+        let relocation_offset = symbol_offset + symbol_table.len();
+        let section_offset = relocation_offset + relocation_table.len();
+        let section_table_len: usize = sections
+            .iter()
+            .map(|(name, contents)| match contents {
+                Contents::Data(_) => name.len() + 14,
+                Contents::Zeroed(_) => name.len() + 10,
+            })
+            .sum();
+        let mut section_table = Vec::new();
+        let mut data = Vec::new();
+        for (name, contents) in sections {
+            push_name(&mut section_table, name);
+            match contents {
+                Contents::Data(bytes) => {
+                    section_table.push(0);
+                    let offset = section_offset + section_table_len + data.len();
+                    for field in [1, offset as u32, bytes.len() as u32] {
+                        section_table.extend_from_slice(&field.to_le_bytes());
+                    }
+                    data.extend_from_slice(bytes);
+                }
+                Contents::Zeroed(size) => {
+                    section_table.push(1);
+                    for field in [1, *size] {
+                        section_table.extend_from_slice(&field.to_le_bytes());
+                    }
+                }
+            }
+        }
+
+        let mut out = MAGIC.to_vec();
+        for field in [
+            FORMAT_VERSION,
+            symbol_offset as u32,
+            symbols.len() as u32,
+            relocation_offset as u32,
+            relocations.len() as u32,
+            section_offset as u32,
+            sections.len() as u32,
+        ] {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+        out.extend(symbol_table);
+        out.extend(relocation_table);
+        out.extend(section_table);
+        out.extend(data);
+        out
+    }
+
+    /// Parses `object_bytes` output, which must be valid.
+    pub(crate) fn object(
+        path: &str,
+        sections: &[(&str, Contents)],
+        symbols: &[(&str, u32, u32)],
+        relocations: &[(&str, u32, u32)],
+    ) -> ObjectFile {
+        let bytes = object_bytes(sections, symbols, relocations);
+        ObjectFile::parse(Path::new(path), &bytes, false).unwrap()
+    }
+
+    #[test]
+    fn parses_sections_symbols_and_relocations() {
+        let obj_file = object(
+            "a.o",
+            &[
+                (".text", Contents::Data(b"\xa1\0\0\0\0")),
+                (".bss", Contents::Zeroed(16)),
+            ],
+            &[("_start", 0, 0), ("buffer", 1, 4)],
+            &[("buffer", 0, 1)],
+        );
+        assert_eq!(obj_file.sections[0].data, b"\xa1\0\0\0\0");
+        assert_eq!(obj_file.sections[1].kind, SectionKind::NoBits);
+        assert_eq!(obj_file.sections[1].size, 16);
+        let symbols: Vec<_> = obj_file
+            .symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.section, symbol.offset))
+            .collect();
+        assert_eq!(symbols, [("_start", 0, 0), ("buffer", 1, 4)]);
+        assert_eq!(obj_file.relocations[0].symbol, "buffer");
+        assert_eq!(obj_file.relocations[0].offset, 1);
+    }
+
+    #[test]
+    fn rejects_symbol_outside_section() {
+        let bytes = object_bytes(
+            &[(".text", Contents::Data(b"\xc3"))],
+            &[("_start", 0, 0xffff_fff0)],
+            &[],
+        );
+        let error = ObjectFile::parse(Path::new("a.o"), &bytes, false)
+            .err()
+            .unwrap();
+        assert!(
+            matches!(
+                error,
+                LinkError::SymbolOutOfRange {
+                    offset: 0xffff_fff0,
+                    ..
+                }
+            ),
+            "unexpected error: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn rejects_sections_too_large_for_address_space() {
+        let bytes = object_bytes(
+            &[
+                (".text", Contents::Data(&[0xc3; 32])),
+                (".bss", Contents::Zeroed(0xffff_fff0)),
+            ],
+            &[],
+            &[],
+        );
+        let error = ObjectFile::parse(Path::new("a.o"), &bytes, false)
+            .err()
+            .unwrap();
+        assert!(
+            matches!(error, LinkError::SectionTooLarge { .. }),
+            "unexpected error: {}",
+            error
+        );
+    }
+
+    fn read_header(bytes: &[u8], accept_v0: bool) -> Result<Header, LinkError> {
+        let mut reader = Reader::new(bytes, 0, Path::new("a.o"), |reader| {
+            LinkError::TruncatedHeader {
+                path: reader.path.to_owned(),
+                len: reader.buffer.len(),
+            }
+        });
+        Header::read(&mut reader, accept_v0)
+    }
+
+    #[test]
+    fn checks_header_signature_and_version() {
+        let mut bytes = object_bytes(&[], &[], &[]);
+        assert_eq!(read_header(&bytes, false).unwrap().version, FORMAT_VERSION);
+
+        bytes[0] = b'X';
+        let error = read_header(&bytes, false).err().unwrap();
+        assert!(
+            matches!(error, LinkError::BadMagic { .. }),
+            "unexpected error: {}",
+            error
+        );
+
+        for version in [0, FORMAT_VERSION + 1] {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&version.to_le_bytes());
+            bytes.extend_from_slice(&[0; 24]);
+            let error = read_header(&bytes, true).err().unwrap();
+            assert!(
+                matches!(error, LinkError::UnsupportedVersion { version: v, .. } if v == version),
+                "unexpected error: {}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn reads_v0_headers_only_when_asked() {
+        let mut bytes = Vec::new();
+        for field in [20u32, 0, 20, 0, 20] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        let error = read_header(&bytes, false).err().unwrap();
+        assert!(
+            matches!(error, LinkError::BadMagic { .. }),
+            "unexpected error: {}",
+            error
+        );
+
+        let header = read_header(&bytes, true).unwrap();
+        assert_eq!(header.version, 0);
+        assert_eq!(header.section_offset, 20);
+        assert_eq!(header.section_length, 1);
+    }
+}
@@ -0,0 +1,846 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::LinkError;
+
+/// A linker script controlling the memory layout, in a small subset of the
+/// GNU ld language:
+///
+/// ```text
+/// ENTRY(reset)
+/// MEMORY {
+///     rom (rx) : ORIGIN = 0x0, LENGTH = 64K
+///     ram (rw) : ORIGIN = 0x10000, LENGTH = 16K
+/// }
+/// SECTIONS {
+///     .text : ALIGN(4) { KEEP(*(.vectors)) *(.text .text.*) } > rom
+///     .data : { *(.data) } > ram
+///     . = ALIGN(16);
+///     __stack_top = ORIGIN(ram) + LENGTH(ram);
+/// }
+/// ```
+///
+/// Unlike GNU ld, `.` is always an absolute address, also inside an output
+/// section, and `MEMORY` must come before any `SECTIONS` that use it. Input
+/// sections no rule matches join the output section of their name, or are
+/// placed after the last output section, grouped as they would be without a
+/// script. There is no separate load address: a flat binary holds every byte
+/// from the lowest section to the end of the highest, so regions with loaded
+/// contents should lie next to each other.
+#[derive(Default)]
+pub struct Script {
+    pub(crate) entry: Option<String>,
+    pub(crate) regions: Vec<MemoryRegion>,
+    pub(crate) commands: Vec<Command>,
+}
+
+pub(crate) struct MemoryRegion {
+    pub(crate) name: String,
+    pub(crate) origin: u32,
+    pub(crate) length: u32,
+}
+
+pub(crate) enum Command {
+    Assign(Assignment),
+    Section(SectionRule),
+}
+
+/// `symbol = expr;`, where a `symbol` of `.` moves the location counter.
+pub(crate) struct Assignment {
+    pub(crate) symbol: String,
+    pub(crate) expr: Expr,
+}
+
+pub(crate) struct SectionRule {
+    pub(crate) name: String,
+    pub(crate) address: Option<Expr>,
+    pub(crate) align: Option<Expr>,
+    pub(crate) contents: Vec<SectionCommand>,
+    pub(crate) region: Option<String>,
+}
+
+pub(crate) enum SectionCommand {
+    Assign(Assignment),
+    Input(InputPattern),
+}
+
+/// `file(section section...)`, with `*` and `?` wildcards in both parts.
+/// Sections matched by a pattern wrapped in `KEEP` are never garbage
+/// collected.
+pub(crate) struct InputPattern {
+    pub(crate) file: String,
+    pub(crate) sections: Vec<String>,
+    pub(crate) keep: bool,
+}
+
+impl InputPattern {
+    pub(crate) fn matches(&self, path: &Path, section: &str) -> bool {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        (glob_match(&self.file, &file_name) || glob_match(&self.file, &path.to_string_lossy()))
+            && self
+                .sections
+                .iter()
+                .any(|pattern| glob_match(pattern, section))
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[u8], text: &[u8]) -> bool {
+        match (pattern.first(), text.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => {
+                matches(&pattern[1..], text) || (!text.is_empty() && matches(pattern, &text[1..]))
+            }
+            (Some(b'?'), Some(_)) => matches(&pattern[1..], &text[1..]),
+            (Some(p), Some(t)) if p == t => matches(&pattern[1..], &text[1..]),
+            _ => false,
+        }
+    }
+    matches(pattern.as_bytes(), text.as_bytes())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+}
+
+impl BinaryOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::And => "&",
+            BinaryOp::Or => "|",
+            BinaryOp::Xor => "^",
+        }
+    }
+}
+
+/// An expression over 32-bit addresses. Arithmetic wraps.
+#[derive(Clone, Debug)]
+pub(crate) enum Expr {
+    Number(u32),
+    Dot,
+    Symbol(String),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// `ALIGN(n)`, the location counter rounded up to a multiple of `n`.
+    Align(Box<Expr>),
+    /// `ALIGN(expr, n)`.
+    AlignTo(Box<Expr>, Box<Expr>),
+    Origin(String),
+    Length(String),
+    Addr(String),
+    SizeOf(String),
+}
+
+/// What an expression can refer to while it is evaluated.
+pub(crate) trait Env {
+    fn dot(&self) -> Option<u32>;
+    fn symbol(&self, name: &str) -> Option<u32>;
+    fn region(&self, name: &str) -> Option<&MemoryRegion>;
+    /// The address and size of an output section that has been placed.
+    fn section(&self, name: &str) -> Option<(u32, u32)>;
+}
+
+/// An environment with nothing in it, for expressions that must be constant.
+struct ConstEnv;
+
+impl Env for ConstEnv {
+    fn dot(&self) -> Option<u32> {
+        None
+    }
+
+    fn symbol(&self, _: &str) -> Option<u32> {
+        None
+    }
+
+    fn region(&self, _: &str) -> Option<&MemoryRegion> {
+        None
+    }
+
+    fn section(&self, _: &str) -> Option<(u32, u32)> {
+        None
+    }
+}
+
+impl Expr {
+    pub(crate) fn eval(&self, env: &dyn Env) -> Result<u32, LinkError> {
+        self.eval_inner(env)
+            .map_err(|reason| LinkError::InvalidExpression {
+                expression: self.to_string(),
+                reason,
+            })
+    }
+
+    fn eval_inner(&self, env: &dyn Env) -> Result<u32, String> {
+        let dot = || {
+            env.dot()
+                .ok_or_else(|| "'.' is not available here".to_owned())
+        };
+        let region = |name: &str| {
+            env.region(name)
+                .ok_or_else(|| format!("no memory region named '{}'", name))
+        };
+        let section = |name: &str| {
+            env.section(name)
+                .ok_or_else(|| format!("section '{}' has not been placed", name))
+        };
+        let align = |value: u32, align: u32| {
+            if align == 0 || !align.is_power_of_two() {
+                Err(format!("alignment {} is not a power of two", align))
+            } else {
+                Ok(value.wrapping_add(align - 1) & !(align - 1))
+            }
+        };
+
+        Ok(match self {
+            Expr::Number(value) => *value,
+            Expr::Dot => dot()?,
+            Expr::Symbol(name) => env
+                .symbol(name)
+                .ok_or_else(|| format!("undefined symbol '{}'", name))?,
+            Expr::Neg(operand) => operand.eval_inner(env)?.wrapping_neg(),
+            Expr::Not(operand) => !operand.eval_inner(env)?,
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval_inner(env)?;
+                let rhs = rhs.eval_inner(env)?;
+                match op {
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                    BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                    BinaryOp::Div => lhs
+                        .checked_div(rhs)
+                        .ok_or_else(|| "division by zero".to_owned())?,
+                    BinaryOp::Rem => lhs
+                        .checked_rem(rhs)
+                        .ok_or_else(|| "division by zero".to_owned())?,
+                    BinaryOp::Shl => lhs.wrapping_shl(rhs),
+                    BinaryOp::Shr => lhs.wrapping_shr(rhs),
+                    BinaryOp::And => lhs & rhs,
+                    BinaryOp::Or => lhs | rhs,
+                    BinaryOp::Xor => lhs ^ rhs,
+                }
+            }
+            Expr::Align(n) => align(dot()?, n.eval_inner(env)?)?,
+            Expr::AlignTo(value, n) => align(value.eval_inner(env)?, n.eval_inner(env)?)?,
+            Expr::Origin(name) => region(name)?.origin,
+            Expr::Length(name) => region(name)?.length,
+            Expr::Addr(name) => section(name)?.0,
+            Expr::SizeOf(name) => section(name)?.1,
+        })
+    }
+
+    /// Adds the names of the symbols the expression refers to to `names`.
+    pub(crate) fn symbols<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expr::Symbol(name) => names.push(name),
+            Expr::Neg(operand) | Expr::Not(operand) | Expr::Align(operand) => {
+                operand.symbols(names)
+            }
+            Expr::Binary(_, lhs, rhs) | Expr::AlignTo(lhs, rhs) => {
+                lhs.symbols(names);
+                rhs.symbols(names);
+            }
+            Expr::Number(_)
+            | Expr::Dot
+            | Expr::Origin(_)
+            | Expr::Length(_)
+            | Expr::Addr(_)
+            | Expr::SizeOf(_) => {}
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(value) => write!(f, "{:#x}", value),
+            Expr::Dot => write!(f, "."),
+            Expr::Symbol(name) => write!(f, "{}", name),
+            Expr::Neg(operand) => write!(f, "-{}", operand),
+            Expr::Not(operand) => write!(f, "~{}", operand),
+            Expr::Binary(op, lhs, rhs) => write!(f, "({} {} {})", lhs, op.symbol(), rhs),
+            Expr::Align(n) => write!(f, "ALIGN({})", n),
+            Expr::AlignTo(value, n) => write!(f, "ALIGN({}, {})", value, n),
+            Expr::Origin(name) => write!(f, "ORIGIN({})", name),
+            Expr::Length(name) => write!(f, "LENGTH({})", name),
+            Expr::Addr(name) => write!(f, "ADDR({})", name),
+            Expr::SizeOf(name) => write!(f, "SIZEOF({})", name),
+        }
+    }
+}
+
+impl Script {
+    /// Whether the rule that claims section `section` of the object at
+    /// `path` wraps its pattern in `KEEP`.
+    pub(crate) fn keeps(&self, path: &Path, section: &str) -> bool {
+        self.commands
+            .iter()
+            .filter_map(|command| match command {
+                Command::Section(rule) => Some(&rule.contents),
+                Command::Assign(_) => None,
+            })
+            .flatten()
+            .find_map(|content| match content {
+                SectionCommand::Input(pattern) if pattern.matches(path, section) => Some(pattern),
+                _ => None,
+            })
+            .is_some_and(|pattern| pattern.keep)
+    }
+
+    /// The symbols the script's expressions refer to but do not assign.
+    pub(crate) fn referenced_symbols(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        let mut assigned = Vec::new();
+        for command in &self.commands {
+            match command {
+                Command::Assign(assignment) => {
+                    assignment.expr.symbols(&mut names);
+                    assigned.push(assignment.symbol.as_str());
+                }
+                Command::Section(rule) => {
+                    for expr in rule.address.iter().chain(&rule.align) {
+                        expr.symbols(&mut names);
+                    }
+                    for content in &rule.contents {
+                        if let SectionCommand::Assign(assignment) = content {
+                            assignment.expr.symbols(&mut names);
+                            assigned.push(assignment.symbol.as_str());
+                        }
+                    }
+                }
+            }
+        }
+        names.retain(|name| !assigned.contains(name));
+        names
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Script, LinkError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|source| LinkError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        Script::parse(path, &source)
+    }
+
+    /// Parses `source`. `path` is only used in error messages.
+    pub fn parse(path: impl AsRef<Path>, source: &str) -> Result<Script, LinkError> {
+        let mut parser = Parser {
+            source: source.as_bytes(),
+            pos: 0,
+            path: path.as_ref().to_owned(),
+        };
+        let mut script = Script {
+            entry: None,
+            regions: Vec::new(),
+            commands: Vec::new(),
+        };
+
+        while parser.peek().is_some() {
+            let keyword = parser.word()?;
+            match keyword.as_str() {
+                "ENTRY" => {
+                    parser.expect(b'(')?;
+                    script.entry = Some(parser.word()?);
+                    parser.expect(b')')?;
+                }
+                "MEMORY" => {
+                    parser.expect(b'{')?;
+                    while !parser.eat(b'}') {
+                        script.regions.push(parser.memory_region()?);
+                    }
+                }
+                "SECTIONS" => {
+                    parser.expect(b'{')?;
+                    while !parser.eat(b'}') {
+                        let command = parser.command()?;
+                        if let Command::Section(SectionRule {
+                            region: Some(region),
+                            ..
+                        }) = &command
+                        {
+                            if !script.regions.iter().any(|r| &r.name == region) {
+                                return Err(
+                                    parser.error(format!("no memory region named '{}'", region))
+                                );
+                            }
+                        }
+                        script.commands.push(command);
+                    }
+                }
+                _ => return Err(parser.error(format!("unknown command '{}'", keyword))),
+            }
+        }
+
+        Ok(script)
+    }
+
+    /// Parses a lone expression, as given to `--defsym`.
+    pub(crate) fn parse_expression(path: &Path, source: &str) -> Result<Expr, LinkError> {
+        let mut parser = Parser {
+            source: source.as_bytes(),
+            pos: 0,
+            path: path.to_owned(),
+        };
+        let expr = parser.expr()?;
+        match parser.peek() {
+            Some(_) => Err(parser.error("unexpected text after expression".to_owned())),
+            None => Ok(expr),
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a [u8],
+    pos: usize,
+    path: PathBuf,
+}
+
+fn is_symbol_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, b'_' | b'.' | b'$')
+}
+
+fn is_pattern_char(c: u8) -> bool {
+    is_symbol_char(c) || matches!(c, b'*' | b'?' | b'-' | b'/' | b'[' | b']')
+}
+
+impl Parser<'_> {
+    fn error(&self, message: String) -> LinkError {
+        let line = self.source[..self.pos]
+            .iter()
+            .filter(|&&c| c == b'\n')
+            .count()
+            + 1;
+        LinkError::ScriptSyntax {
+            path: self.path.clone(),
+            line,
+            message,
+        }
+    }
+
+    /// Skips whitespace and comments, then returns the next character.
+    fn peek(&mut self) -> Option<u8> {
+        loop {
+            while self
+                .source
+                .get(self.pos)
+                .is_some_and(|c| c.is_ascii_whitespace())
+            {
+                self.pos += 1;
+            }
+            if !self.source[self.pos..].starts_with(b"/*") {
+                return self.source.get(self.pos).copied();
+            }
+            self.pos = match self.source[self.pos + 2..]
+                .windows(2)
+                .position(|w| w == b"*/")
+            {
+                Some(end) => self.pos + 2 + end + 2,
+                None => self.source.len(),
+            };
+        }
+    }
+
+    fn peek_str(&mut self, s: &str) -> bool {
+        self.peek();
+        self.source[self.pos..].starts_with(s.as_bytes())
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), LinkError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", c as char)))
+        }
+    }
+
+    fn take_while(&mut self, accept: fn(u8) -> bool) -> String {
+        self.peek();
+        let start = self.pos;
+        while self.source.get(self.pos).is_some_and(|&c| accept(c)) {
+            self.pos += 1;
+        }
+        String::from_utf8_lossy(&self.source[start..self.pos]).into_owned()
+    }
+
+    fn word(&mut self) -> Result<String, LinkError> {
+        let word = self.take_while(is_symbol_char);
+        if word.is_empty() {
+            return Err(self.error("expected a name".to_owned()));
+        }
+        Ok(word)
+    }
+
+    fn memory_region(&mut self) -> Result<MemoryRegion, LinkError> {
+        let name = self.word()?;
+        if self.eat(b'(') {
+            self.take_while(|c| c != b')');
+            self.expect(b')')?;
+        }
+        self.expect(b':')?;
+        let origin = self.region_attribute(&["ORIGIN", "org", "o"])?;
+        self.eat(b',');
+        let length = self.region_attribute(&["LENGTH", "len", "l"])?;
+        self.eat(b',');
+        Ok(MemoryRegion {
+            name,
+            origin,
+            length,
+        })
+    }
+
+    fn region_attribute(&mut self, names: &[&str]) -> Result<u32, LinkError> {
+        let word = self.word()?;
+        if !names.contains(&word.as_str()) {
+            return Err(self.error(format!("expected {}", names[0])));
+        }
+        self.expect(b'=')?;
+        self.expr()?.eval(&ConstEnv)
+    }
+
+    /// An assignment to `target`, whose name has already been read.
+    fn assignment(&mut self, target: String) -> Result<Assignment, LinkError> {
+        let compound = if self.peek_str("+=") {
+            self.pos += 1;
+            Some(BinaryOp::Add)
+        } else if self.peek_str("-=") {
+            self.pos += 1;
+            Some(BinaryOp::Sub)
+        } else {
+            None
+        };
+        self.expect(b'=')?;
+        let mut expr = self.expr()?;
+        self.expect(b';')?;
+        if let Some(op) = compound {
+            let current = if target == "." {
+                Expr::Dot
+            } else {
+                Expr::Symbol(target.clone())
+            };
+            expr = Expr::Binary(op, Box::new(current), Box::new(expr));
+        }
+        Ok(Assignment {
+            symbol: target,
+            expr,
+        })
+    }
+
+    fn is_assignment(&mut self) -> bool {
+        (self.peek_str("=") && !self.peek_str("==")) || self.peek_str("+=") || self.peek_str("-=")
+    }
+
+    fn command(&mut self) -> Result<Command, LinkError> {
+        let name = self.word()?;
+        if self.is_assignment() {
+            return Ok(Command::Assign(self.assignment(name)?));
+        }
+
+        let address = if self.peek() == Some(b':') {
+            None
+        } else {
+            Some(self.expr()?)
+        };
+        self.expect(b':')?;
+        let align = if self.peek_str("ALIGN") {
+            self.word()?;
+            self.expect(b'(')?;
+            let align = self.expr()?;
+            self.expect(b')')?;
+            Some(align)
+        } else {
+            None
+        };
+
+        self.expect(b'{')?;
+        let mut contents = Vec::new();
+        while !self.eat(b'}') {
+            contents.push(self.section_command()?);
+        }
+
+        let region = if self.eat(b'>') {
+            Some(self.word()?)
+        } else {
+            None
+        };
+
+        Ok(Command::Section(SectionRule {
+            name,
+            address,
+            align,
+            contents,
+            region,
+        }))
+    }
+
+    fn section_command(&mut self) -> Result<SectionCommand, LinkError> {
+        let token = self.take_while(is_pattern_char);
+        if token.is_empty() {
+            return Err(self.error("expected an input section pattern".to_owned()));
+        }
+        if self.is_assignment() {
+            return Ok(SectionCommand::Assign(self.assignment(token)?));
+        }
+        if token == "KEEP" {
+            self.expect(b'(')?;
+            let file = self.take_while(is_pattern_char);
+            let pattern = self.input_pattern(file, true)?;
+            self.expect(b')')?;
+            return Ok(SectionCommand::Input(pattern));
+        }
+        Ok(SectionCommand::Input(self.input_pattern(token, false)?))
+    }
+
+    fn input_pattern(&mut self, file: String, keep: bool) -> Result<InputPattern, LinkError> {
+        if file.is_empty() {
+            return Err(self.error("expected a file pattern".to_owned()));
+        }
+        self.expect(b'(')?;
+        let mut sections = Vec::new();
+        while !self.eat(b')') {
+            let section = self.take_while(is_pattern_char);
+            if section.is_empty() {
+                return Err(self.error("expected a section pattern".to_owned()));
+            }
+            sections.push(section);
+        }
+        Ok(InputPattern {
+            file,
+            sections,
+            keep,
+        })
+    }
+
+    fn expr(&mut self) -> Result<Expr, LinkError> {
+        self.binary(0)
+    }
+
+    /// Operators from loosest to tightest binding.
+    const PRECEDENCE: [&'static [(&'static str, BinaryOp)]; 6] = [
+        &[("|", BinaryOp::Or)],
+        &[("^", BinaryOp::Xor)],
+        &[("&", BinaryOp::And)],
+        &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+        &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+        &[
+            ("*", BinaryOp::Mul),
+            ("/", BinaryOp::Div),
+            ("%", BinaryOp::Rem),
+        ],
+    ];
+
+    fn binary(&mut self, level: usize) -> Result<Expr, LinkError> {
+        if level == Self::PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        'operators: loop {
+            for &(symbol, op) in Self::PRECEDENCE[level] {
+                if self.peek_str(symbol) && !self.peek_str("+=") && !self.peek_str("-=") {
+                    self.pos += symbol.len();
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+                    continue 'operators;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, LinkError> {
+        if self.eat(b'-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat(b'~') {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(b'(') {
+            let expr = self.expr()?;
+            self.expect(b')')?;
+            return Ok(expr);
+        }
+
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => self.number(),
+            Some(c) if is_symbol_char(c) => {
+                let name = self.word()?;
+                if name == "." {
+                    return Ok(Expr::Dot);
+                }
+                if self.peek() != Some(b'(') {
+                    return Ok(Expr::Symbol(name));
+                }
+                self.function(&name)
+            }
+            _ => Err(self.error("expected an expression".to_owned())),
+        }
+    }
+
+    fn function(&mut self, name: &str) -> Result<Expr, LinkError> {
+        self.expect(b'(')?;
+        let expr = match name {
+            "ALIGN" => {
+                let first = self.expr()?;
+                if self.eat(b',') {
+                    Expr::AlignTo(Box::new(first), Box::new(self.expr()?))
+                } else {
+                    Expr::Align(Box::new(first))
+                }
+            }
+            "ORIGIN" => Expr::Origin(self.word()?),
+            "LENGTH" => Expr::Length(self.word()?),
+            "ADDR" => Expr::Addr(self.word()?),
+            "SIZEOF" => Expr::SizeOf(self.word()?),
+            _ => return Err(self.error(format!("unknown function '{}'", name))),
+        };
+        self.expect(b')')?;
+        Ok(expr)
+    }
+
+    fn number(&mut self) -> Result<Expr, LinkError> {
+        let text = self.take_while(|c| c.is_ascii_alphanumeric());
+        let (digits, multiplier) = match text.as_bytes().last() {
+            Some(b'K') => (&text[..text.len() - 1], 1024),
+            Some(b'M') => (&text[..text.len() - 1], 1024 * 1024),
+            _ => (text.as_str(), 1),
+        };
+        let value = match digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => digits.parse(),
+        };
+        value
+            .ok()
+            .and_then(|value| value.checked_mul(multiplier))
+            .map(Expr::Number)
+            .ok_or_else(|| self.error(format!("invalid number '{}'", text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Script, LinkError> {
+        Script::parse(Path::new("test.ld"), source)
+    }
+
+    #[test]
+    fn parses_documented_example() {
+        let script = parse(
+            "ENTRY(reset)
+            MEMORY {
+                rom (rx) : ORIGIN = 0x0, LENGTH = 64K
+                ram (rw) : ORIGIN = 0x10000, LENGTH = 16K
+            }
+            SECTIONS {
+                .text : ALIGN(4) { KEEP(*(.vectors)) *(.text .text.*) } > rom
+                .data : { *(.data) } > ram
+                . = ALIGN(16);
+                __stack_top = ORIGIN(ram) + LENGTH(ram);
+            }",
+        )
+        .unwrap();
+
+        assert_eq!(script.entry.as_deref(), Some("reset"));
+        let regions: Vec<_> = script
+            .regions
+            .iter()
+            .map(|region| (region.name.as_str(), region.origin, region.length))
+            .collect();
+        assert_eq!(regions, [("rom", 0, 0x10000), ("ram", 0x1_0000, 0x4000)]);
+
+        let [Command::Section(text), Command::Section(data), Command::Assign(dot), Command::Assign(stack_top)] =
+            &script.commands[..]
+        else {
+            panic!("unexpected commands");
+        };
+        assert_eq!(text.name, ".text");
+        assert_eq!(text.align.as_ref().unwrap().to_string(), "0x4");
+        assert_eq!(text.region.as_deref(), Some("rom"));
+        let [SectionCommand::Input(vectors), SectionCommand::Input(code)] = &text.contents[..]
+        else {
+            panic!("unexpected .text contents");
+        };
+        assert!(vectors.keep);
+        assert_eq!(vectors.sections, [".vectors"]);
+        assert!(!code.keep);
+        assert_eq!(code.sections, [".text", ".text.*"]);
+        assert!(code.matches(Path::new("lib/a.o"), ".text.startup"));
+        assert!(!code.matches(Path::new("lib/a.o"), ".data"));
+        assert_eq!(data.region.as_deref(), Some("ram"));
+
+        assert_eq!(dot.symbol, ".");
+        assert_eq!(dot.expr.to_string(), "ALIGN(0x10)");
+        assert_eq!(stack_top.symbol, "__stack_top");
+        assert_eq!(stack_top.expr.to_string(), "(ORIGIN(ram) + LENGTH(ram))");
+        assert!(script.keeps(Path::new("a.o"), ".vectors"));
+        assert!(!script.keeps(Path::new("a.o"), ".text"));
+    }
+
+    #[test]
+    fn reports_syntax_error_line() {
+        let error = parse("SECTIONS {\n  .text : { *(.text) \n}\n")
+            .err()
+            .unwrap();
+        let LinkError::ScriptSyntax { line, .. } = error else {
+            panic!("unexpected error: {}", error);
+        };
+        assert_eq!(line, 4);
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let expr = Script::parse_expression(Path::new("--defsym"), "0x100 + 2 * 3 << 1").unwrap();
+        assert_eq!(expr.eval(&ConstEnv).unwrap(), (0x100 + 2 * 3) << 1);
+        assert!(Script::parse_expression(Path::new("--defsym"), "1 2").is_err());
+        assert!(Script::parse_expression(Path::new("--defsym"), "table")
+            .unwrap()
+            .eval(&ConstEnv)
+            .is_err());
+    }
+
+    #[test]
+    fn lists_referenced_symbols() {
+        let script = parse(
+            "SECTIONS {
+                .text start : { *(.text) end = .; }
+                total = end - start + extra;
+            }",
+        )
+        .unwrap();
+        assert_eq!(script.referenced_symbols(), ["start", "start", "extra"]);
+    }
+}